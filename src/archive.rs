@@ -0,0 +1,283 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+// Readers for zip and (gzipped) tar archives of sequence files. Only
+// regular files are read; zip64 and encrypted zip archives aren't
+// supported.
+//
+use std::io::Read;
+
+use needletail::errors::ParseError;
+
+use crate::common::*;
+use crate::reader::SeqReader;
+
+pub const ARCHIVE_EXTENSIONS: [&str; 4] = [".zip", ".tar", ".tar.gz", ".tgz"];
+
+// Size of the pieces that archived files are parsed in
+const ENTRY_CHUNK_SIZE: usize = 1 << 20;
+
+/// Returns true if `file_name` has an archive extension.
+pub fn is_archive(file_name: &str) -> bool {
+    ARCHIVE_EXTENSIONS.iter().any(|ext| file_name.ends_with(ext))
+}
+
+fn archive_err(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Returns the name of the archived file at `path`, or None if it
+// should be skipped. The directories are kept in the name so that
+// files with the same name in different directories stay separate.
+fn entry_name<'a>(path: &'a str, keep: &dyn Fn(&str) -> bool) -> Option<&'a str> {
+    let path = path.trim_start_matches("./");
+    let name = path.rsplit('/').next().unwrap_or(path);
+    // Skip the metadata macOS adds to archives
+    if path.starts_with("__MACOSX/") || name.starts_with('.') || !keep(name) {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+fn read_entry(
+    name: &str,
+    mut entry: impl Read,
+) -> Result<SeqData, ParseError> {
+    let mut reader = SeqReader::default();
+    let mut buf = vec![0_u8; ENTRY_CHUNK_SIZE];
+    loop {
+        let n = entry.read(&mut buf)?;
+        if n == 0 {
+            break
+        }
+        reader.push(&buf[..n])?;
+    }
+    reader.finish(name)
+}
+
+fn le_u16(bytes: &[u8], pos: usize) -> usize {
+    u16::from_le_bytes([bytes[pos], bytes[pos + 1]]) as usize
+}
+
+fn le_u32(bytes: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize
+}
+
+fn read_zip(
+    contents: &[u8],
+    keep: &dyn Fn(&str) -> bool,
+) -> Result<Vec<SeqData>, ParseError> {
+    let truncated = || archive_err("Zip archive is truncated.");
+
+    // The central directory is listed at the end of the archive
+    let eocd = (0..contents.len().saturating_sub(21)).rev()
+        .find(|pos| contents[*pos..].starts_with(&[0x50, 0x4B, 0x05, 0x06]))
+        .ok_or(archive_err("Zip archive has no central directory."))?;
+    let n_entries = le_u16(contents, eocd + 10);
+    let mut pos = le_u32(contents, eocd + 16);
+    if pos == u32::MAX as usize {
+        return Err(archive_err("Zip64 archives are not supported.").into())
+    }
+
+    let mut seq_data: Vec<SeqData> = Vec::new();
+    for _ in 0..n_entries {
+        let header = contents.get(pos..(pos + 46)).ok_or_else(truncated)?;
+        if !header.starts_with(&[0x50, 0x4B, 0x01, 0x02]) {
+            return Err(archive_err("Zip archive has a malformed central directory.").into())
+        }
+        let flags = le_u16(header, 8);
+        let method = le_u16(header, 10);
+        let compressed_size = le_u32(header, 20);
+        let name_len = le_u16(header, 28);
+        let entry_len = 46 + name_len + le_u16(header, 30) + le_u16(header, 32);
+        let offset = le_u32(header, 42);
+        let path = contents.get((pos + 46)..(pos + 46 + name_len)).ok_or_else(truncated)?;
+        let path = String::from_utf8_lossy(path).to_string();
+        pos += entry_len;
+
+        let name = match entry_name(&path, keep) {
+            Some(name) if !path.ends_with('/') => name,
+            _ => continue,
+        };
+        if flags & 1 == 1 {
+            return Err(archive_err(&("Zip archive entry ".to_string() + &path + " is encrypted.")).into())
+        }
+
+        let local = contents.get(offset..(offset + 30)).ok_or_else(truncated)?;
+        let start = offset + 30 + le_u16(local, 26) + le_u16(local, 28);
+        let data = contents.get(start..(start + compressed_size)).ok_or_else(truncated)?;
+        let entry = match method {
+            0 => read_entry(name, data)?,
+            8 => read_entry(name, flate2::read::DeflateDecoder::new(data))?,
+            _ => return Err(archive_err(&("Zip archive entry ".to_string() + &path + " uses an unsupported compression method.")).into()),
+        };
+        seq_data.push(entry);
+    }
+    Ok(seq_data)
+}
+
+// Parses a NUL or space terminated field of a tar header
+fn tar_field(field: &[u8]) -> String {
+    let end = field.iter().position(|x| *x == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).trim().to_string()
+}
+
+fn read_tar(
+    mut input: impl Read,
+    keep: &dyn Fn(&str) -> bool,
+) -> Result<Vec<SeqData>, ParseError> {
+    let mut seq_data: Vec<SeqData> = Vec::new();
+    let mut header = [0_u8; 512];
+    // Set by GNU and pax extended headers for the next entry
+    let mut long_name: Option<String> = None;
+    loop {
+        if input.read_exact(&mut header).is_err() || header.iter().all(|x| *x == 0) {
+            break
+        }
+        let size = usize::from_str_radix(&tar_field(&header[124..136]), 8)
+            .map_err(|_| archive_err("Tar archive has a malformed header."))?;
+        let padding = (512 - size % 512) % 512;
+        let kind = header[156];
+        let path = long_name.take().unwrap_or_else(|| {
+            let name = tar_field(&header[0..100]);
+            let prefix = if &header[257..262] == b"ustar" { tar_field(&header[345..500]) } else { String::new() };
+            if prefix.is_empty() { name } else { prefix + "/" + &name }
+        });
+
+        let mut entry = (&mut input).take(size as u64);
+        match kind {
+            b'L' | b'x' => {
+                let mut data: Vec<u8> = Vec::with_capacity(size);
+                entry.read_to_end(&mut data)?;
+                long_name = if kind == b'L' {
+                    Some(tar_field(&data))
+                } else {
+                    // Records are "<length> <key>=<value>\n"
+                    String::from_utf8_lossy(&data).lines()
+                        .filter_map(|record| record.split_once(' ').map(|(_, x)| x))
+                        .find_map(|record| record.strip_prefix("path=").map(|x| x.to_string()))
+                };
+            },
+            b'0' | 0 => match entry_name(&path, keep) {
+                Some(name) => seq_data.push(read_entry(name, &mut entry)?),
+                None => { std::io::copy(&mut entry, &mut std::io::sink())?; },
+            },
+            _ => { std::io::copy(&mut entry, &mut std::io::sink())?; },
+        }
+        if entry.limit() > 0 {
+            return Err(archive_err("Tar archive is truncated.").into())
+        }
+        std::io::copy(&mut (&mut input).take(padding as u64), &mut std::io::sink())?;
+    }
+    Ok(seq_data)
+}
+
+/// Reads the sequence files in a zip or tar archive whose names `keep`
+/// accepts into separate entries.
+pub fn read_archive(
+    contents: &[u8],
+    keep: &dyn Fn(&str) -> bool,
+) -> Result<Vec<SeqData>, ParseError> {
+    if contents.starts_with(&[0x50, 0x4B, 0x03, 0x04]) {
+        read_zip(contents, keep)
+    } else if contents.starts_with(&[0x1F, 0x8B]) {
+        read_tar(flate2::read::MultiGzDecoder::new(contents), keep)
+    } else {
+        read_tar(contents, keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn read_tar_and_zip_archives() {
+        use super::read_archive;
+        use std::io::Write;
+
+        let fasta = b">contig\nACGTACGT\n";
+        let keep = |name: &str| name.ends_with(".fasta");
+
+        // Tar with a sequence file and a file that is skipped
+        let mut tar: Vec<u8> = Vec::new();
+        for (name, data) in [("dir/a.fasta", &fasta[..]), ("README", b"readme")] {
+            let mut header = [0_u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.extend_from_slice(&[0_u8; 1024]);
+        let got = read_archive(&tar, &keep).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].file_name, "dir/a.fasta");
+        assert_eq!(got[0].contigs[0].seq, b"ACGTACGT");
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&tar).unwrap();
+        assert_eq!(read_archive(&gz.finish().unwrap(), &keep).unwrap().len(), 1);
+
+        // Zip with one deflated entry
+        let mut deflate = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(fasta).unwrap();
+        let data = deflate.finish().unwrap();
+        let name = b"b.fasta";
+        let mut zip: Vec<u8> = Vec::new();
+        zip.extend_from_slice(&[0x50, 0x4B, 0x03, 0x04]);
+        zip.extend_from_slice(&[0_u8; 22]);
+        zip.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&[0_u8; 2]);
+        zip.extend_from_slice(name);
+        zip.extend_from_slice(&data);
+        let cd_offset = zip.len();
+        let mut central = vec![0_u8; 46];
+        central[..4].copy_from_slice(&[0x50, 0x4B, 0x01, 0x02]);
+        central[10..12].copy_from_slice(&8_u16.to_le_bytes());
+        central[20..24].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        central[28..30].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        zip.extend_from_slice(&central);
+        zip.extend_from_slice(name);
+        let mut eocd = vec![0_u8; 22];
+        eocd[..4].copy_from_slice(&[0x50, 0x4B, 0x05, 0x06]);
+        eocd[10..12].copy_from_slice(&1_u16.to_le_bytes());
+        eocd[16..20].copy_from_slice(&(cd_offset as u32).to_le_bytes());
+        zip.extend_from_slice(&eocd);
+        let got = read_archive(&zip, &keep).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].file_name, "b.fasta");
+        assert_eq!(got[0].contigs[0].seq, b"ACGTACGT");
+    }
+
+    #[test]
+    fn read_archive_keeps_files_with_the_same_name() {
+        use super::read_archive;
+
+        let mut tar: Vec<u8> = Vec::new();
+        for (name, data) in [("s1/contigs.fasta", &b">a\nACGT\n"[..]), ("./s2/contigs.fasta", b">b\nTTTT\n")] {
+            let mut header = [0_u8; 512];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = b'0';
+            tar.extend_from_slice(&header);
+            tar.extend_from_slice(data);
+            tar.resize(tar.len().div_ceil(512) * 512, 0);
+        }
+        tar.extend_from_slice(&[0_u8; 1024]);
+        let got = read_archive(&tar, &|name: &str| name.ends_with(".fasta")).unwrap();
+        let names: Vec<&str> = got.iter().map(|x| x.file_name.as_str()).collect();
+        assert_eq!(names, vec!["s1/contigs.fasta", "s2/contigs.fasta"]);
+        assert_eq!(got[1].contigs[0].seq, b"TTTT");
+    }
+}
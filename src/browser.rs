@@ -0,0 +1,100 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadErr {
+    pub code: usize,
+    pub message: String,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl From<wasm_bindgen::JsValue> for DownloadErr {
+    fn from(e: wasm_bindgen::JsValue) -> DownloadErr {
+        DownloadErr{ code: 1, message: format!("{:?}", e) }
+    }
+}
+
+/// Offers `data` to the user as a file download named `file_name`.
+#[cfg(target_arch = "wasm32")]
+pub fn download_bytes(
+    file_name: &str,
+    data: &[u8],
+) -> Result<(), DownloadErr> {
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(data));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window().and_then(|window| window.document());
+    if document.is_none() {
+        return Err(DownloadErr{ code: 0, message: "No document to attach the download to.".to_string() })
+    }
+    let link = document.unwrap().create_element("a")?.unchecked_into::<web_sys::HtmlAnchorElement>();
+    link.set_href(&url);
+    link.set_download(file_name);
+    link.click();
+
+    web_sys::Url::revoke_object_url(&url)?;
+    Ok(())
+}
+
+/// Writes `data` to `file_name` in the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn download_bytes(
+    file_name: &str,
+    data: &[u8],
+) -> Result<(), DownloadErr> {
+    std::fs::write(file_name, data).map_err(|e| DownloadErr{ code: 1, message: e.to_string() })
+}
+
+/// Reads `file_name` from `file_engine` in chunks of at most `chunk_size`
+/// bytes and passes them to `on_chunk` in order.
+#[cfg(target_arch = "wasm32")]
+pub async fn read_file_chunks<E: From<std::io::Error>>(
+    file_engine: &std::sync::Arc<dyn dioxus::html::FileEngine>,
+    file_name: &str,
+    chunk_size: usize,
+    mut on_chunk: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let js_err = |e: wasm_bindgen::JsValue| std::io::Error::other(format!("Couldn't read {}: {:?}", file_name, e));
+
+    let file = file_engine.get_native_file(file_name).await
+        .and_then(|file| file.downcast::<web_sys::File>().ok())
+        .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, "Couldn't open ".to_string() + file_name))?;
+    let size = file.size();
+    let mut start = 0_f64;
+    while start < size {
+        let end = (start + chunk_size as f64).min(size);
+        let blob = file.slice_with_f64_and_f64(start, end).map_err(js_err)?;
+        let buffer = wasm_bindgen_futures::JsFuture::from(blob.array_buffer()).await.map_err(js_err)?;
+        on_chunk(&js_sys::Uint8Array::new(&buffer).to_vec())?;
+        start = end;
+    }
+    Ok(())
+}
+
+/// Reads `file_name` from `file_engine` and passes it to `on_chunk` in
+/// chunks of at most `chunk_size` bytes.
+#[cfg(not(target_arch = "wasm32"))]
+pub async fn read_file_chunks<E: From<std::io::Error>>(
+    file_engine: &std::sync::Arc<dyn dioxus::html::FileEngine>,
+    file_name: &str,
+    chunk_size: usize,
+    on_chunk: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let contents = file_engine.read_file(file_name).await
+        .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, "Couldn't open ".to_string() + file_name))?;
+    contents.chunks(chunk_size).try_for_each(on_chunk)
+}
@@ -0,0 +1,379 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+// Persistent cache for built indexes. Entries are stored in IndexedDB
+// when running in the browser and in the user's cache directory on
+// native targets, using the format from `crate::util::serialize_indexes`.
+// Indexes are only built, loaded and used by the worker, which keeps its
+// own in-memory copies, so every cache operation including listing and
+// removing entries runs there.
+//
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::*;
+use crate::opts::BuildOpts;
+
+// Indexes of the current inputs are also kept in memory, so that
+// changing the query files only indexes the added or changed files and
+// jobs can refer to them by their key.
+thread_local! {
+    static LOADED: RefCell<HashMap<String, Vec<IndexData>>> = RefCell::new(HashMap::new());
+}
+
+/// Drops the in-memory indexes that aren't stored under one of `keys`.
+pub fn retain_loaded(
+    keys: &[String],
+) {
+    LOADED.with(|loaded| loaded.borrow_mut().retain(|key, _| keys.contains(key)));
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheErr {
+    pub code: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    pub file_name: String,
+    pub kmer_size: u32,
+    pub bytes: usize,
+}
+
+// 64-bit FNV-1a, stable across builds unlike std's DefaultHasher.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+pub fn content_hash(
+    seq_data: &SeqData,
+) -> u64 {
+    seq_data.contigs.iter().fold(0xcbf29ce484222325, |hash, contig| {
+        let hash = fnv1a(hash, &(contig.name.len() as u64).to_le_bytes());
+        let hash = fnv1a(hash, contig.name.as_bytes());
+        let hash = fnv1a(hash, &(contig.seq.len() as u64).to_le_bytes());
+        let hash = fnv1a(hash, &contig.seq);
+        let hash = fnv1a(hash, &contig.qual);
+        contig.soft_masked.iter().fold(hash, |hash, (start, end)| {
+            fnv1a(fnv1a(hash, &(*start as u64).to_le_bytes()), &(*end as u64).to_le_bytes())
+        })
+    })
+}
+
+/// Cache key for the index of `seq_data` built with `build_opts`,
+/// either from all contigs or `separately` from each contig.
+pub fn cache_key(
+    seq_data: &SeqData,
+    build_opts: &BuildOpts,
+    separately: bool,
+) -> String {
+    format!("{:016x}-k{}-p{}-d{}-q{}-a{}-m{}-l{}-{}",
+            content_hash(seq_data),
+            build_opts.kmer_size,
+            build_opts.prefix_precalc,
+            build_opts.dedup_batches as u8,
+            build_opts.min_base_quality,
+            build_opts.min_kmer_count,
+            build_opts.exclude_soft_masked as u8,
+            build_opts.min_contig_len,
+            if separately { "contigs" } else { "file" })
+}
+
+/// Cache key for the indexes in a prebuilt index file.
+pub fn file_key(
+    contents: &[u8],
+) -> String {
+    format!("{:016x}-index-file", fnv1a(0xcbf29ce484222325, contents))
+}
+
+/// Returns the cached indexes stored under `key`, if any, keeping them
+/// in memory.
+pub async fn get(
+    key: &str,
+) -> Option<Vec<IndexData>> {
+    if let Some(indexes) = LOADED.with(|loaded| loaded.borrow().get(key).cloned()) {
+        return Some(indexes)
+    }
+    let bytes = backend::get(key).await.ok()??;
+    let indexes = crate::util::load_indexes(&mut bytes.as_slice()).ok()?;
+    LOADED.with(|loaded| loaded.borrow_mut().insert(key.to_string(), indexes.clone()));
+    Some(indexes)
+}
+
+pub async fn put(
+    key: &str,
+    indexes: Vec<IndexData>,
+) -> Result<(), CacheErr> {
+    let mut bytes: Vec<u8> = Vec::new();
+    crate::util::serialize_indexes(&indexes, &mut bytes).map_err(|e| CacheErr{ code: 1, message: e.to_string() })?;
+    put_serialized(key, indexes, &bytes).await
+}
+
+/// Stores `indexes` under `key`, with `bytes` holding them serialized.
+pub async fn put_serialized(
+    key: &str,
+    indexes: Vec<IndexData>,
+    bytes: &[u8],
+) -> Result<(), CacheErr> {
+    if indexes.is_empty() {
+        return Err(CacheErr{ code: 1, message: "Argument `indexes` is empty.".to_string() })
+    }
+    let entry = CacheEntry {
+        key: key.to_string(),
+        file_name: indexes[0].file_name.clone(),
+        kmer_size: indexes[0].build_opts.kmer_size,
+        bytes: bytes.len(),
+    };
+    LOADED.with(|loaded| loaded.borrow_mut().insert(key.to_string(), indexes));
+    backend::put(&entry, bytes).await
+}
+
+pub async fn list() -> Result<Vec<CacheEntry>, CacheErr> {
+    backend::list().await
+}
+
+pub async fn evict(
+    key: &str,
+) -> Result<(), CacheErr> {
+    LOADED.with(|loaded| loaded.borrow_mut().remove(key));
+    backend::evict(key).await
+}
+
+pub async fn clear() -> Result<(), CacheErr> {
+    LOADED.with(|loaded| loaded.borrow_mut().clear());
+    backend::clear().await
+}
+
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::*;
+
+    use js_sys::{Object, Reflect, Uint8Array};
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use web_sys::{IdbDatabase, IdbFactory, IdbRequest, IdbTransactionMode};
+
+    const DB_NAME: &str = "kbo-gui";
+    const DB_VERSION: u32 = 1;
+    const DATA_STORE: &str = "indexes";
+    const META_STORE: &str = "entries";
+
+    impl From<JsValue> for CacheErr {
+        fn from(e: JsValue) -> CacheErr {
+            CacheErr{ code: 1, message: format!("{:?}", e) }
+        }
+    }
+
+    // Attaches the handlers immediately so that several requests in one
+    // transaction can be queued before awaiting any of them.
+    fn wait_for(
+        request: &IdbRequest,
+    ) -> wasm_bindgen_futures::JsFuture {
+        let promise = js_sys::Promise::new(&mut |resolve, reject| {
+            let req = request.clone();
+            let onsuccess = Closure::once_into_js(move |_: web_sys::Event| {
+                let _ = resolve.call1(&JsValue::NULL, &req.result().unwrap_or(JsValue::UNDEFINED));
+            });
+            let onerror = Closure::once_into_js(move |_: web_sys::Event| {
+                let _ = reject.call1(&JsValue::NULL, &JsValue::from_str("IndexedDB request failed."));
+            });
+            request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+            request.set_onerror(Some(onerror.unchecked_ref()));
+        });
+        wasm_bindgen_futures::JsFuture::from(promise)
+    }
+
+    async fn open() -> Result<IdbDatabase, CacheErr> {
+        // Both the window and the worker have their own `indexedDB`
+        let factory = Reflect::get(&js_sys::global(), &"indexedDB".into()).ok().and_then(|x| x.dyn_into::<IdbFactory>().ok());
+        if factory.is_none() {
+            return Err(CacheErr{ code: 2, message: "IndexedDB is not available.".to_string() })
+        }
+        let request = factory.unwrap().open_with_u32(DB_NAME, DB_VERSION)?;
+
+        let req = request.clone();
+        let onupgradeneeded = Closure::once_into_js(move |_: web_sys::Event| {
+            if let Ok(db) = req.result().map(|db| db.unchecked_into::<IdbDatabase>()) {
+                let _ = db.create_object_store(DATA_STORE);
+                let _ = db.create_object_store(META_STORE);
+            }
+        });
+        request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+
+        Ok(wait_for(&request).await?.unchecked_into::<IdbDatabase>())
+    }
+
+    pub async fn get(
+        key: &str,
+    ) -> Result<Option<Vec<u8>>, CacheErr> {
+        let db = open().await?;
+        let store = db.transaction_with_str(DATA_STORE)?.object_store(DATA_STORE)?;
+        let value = wait_for(&store.get(&JsValue::from_str(key))?).await?;
+        db.close();
+        Ok(value.dyn_into::<Uint8Array>().ok().map(|bytes| bytes.to_vec()))
+    }
+
+    pub async fn put(
+        entry: &CacheEntry,
+        bytes: &[u8],
+    ) -> Result<(), CacheErr> {
+        let meta = Object::new();
+        Reflect::set(&meta, &"file_name".into(), &entry.file_name.as_str().into())?;
+        Reflect::set(&meta, &"kmer_size".into(), &entry.kmer_size.into())?;
+        Reflect::set(&meta, &"bytes".into(), &(entry.bytes as f64).into())?;
+
+        let db = open().await?;
+        let stores = js_sys::Array::of2(&DATA_STORE.into(), &META_STORE.into());
+        let transaction = db.transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite)?;
+        let key = JsValue::from_str(&entry.key);
+        let put_data = wait_for(&transaction.object_store(DATA_STORE)?.put_with_key(&Uint8Array::from(bytes), &key)?);
+        let put_meta = wait_for(&transaction.object_store(META_STORE)?.put_with_key(&meta, &key)?);
+        put_data.await?;
+        put_meta.await?;
+        db.close();
+        Ok(())
+    }
+
+    pub async fn list() -> Result<Vec<CacheEntry>, CacheErr> {
+        let db = open().await?;
+        let store = db.transaction_with_str(META_STORE)?.object_store(META_STORE)?;
+        let get_keys = wait_for(&store.get_all_keys()?);
+        let get_metas = wait_for(&store.get_all()?);
+        let keys = js_sys::Array::from(&get_keys.await?);
+        let metas = js_sys::Array::from(&get_metas.await?);
+        db.close();
+
+        Ok(keys.iter().zip(metas.iter()).map(|(key, meta)| {
+            CacheEntry {
+                key: key.as_string().unwrap_or_default(),
+                file_name: Reflect::get(&meta, &"file_name".into()).ok().and_then(|x| x.as_string()).unwrap_or_default(),
+                kmer_size: Reflect::get(&meta, &"kmer_size".into()).ok().and_then(|x| x.as_f64()).unwrap_or_default() as u32,
+                bytes: Reflect::get(&meta, &"bytes".into()).ok().and_then(|x| x.as_f64()).unwrap_or_default() as usize,
+            }
+        }).collect())
+    }
+
+    pub async fn evict(
+        key: &str,
+    ) -> Result<(), CacheErr> {
+        let db = open().await?;
+        let stores = js_sys::Array::of2(&DATA_STORE.into(), &META_STORE.into());
+        let transaction = db.transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite)?;
+        let key = JsValue::from_str(key);
+        let delete_data = wait_for(&transaction.object_store(DATA_STORE)?.delete(&key)?);
+        let delete_meta = wait_for(&transaction.object_store(META_STORE)?.delete(&key)?);
+        delete_data.await?;
+        delete_meta.await?;
+        db.close();
+        Ok(())
+    }
+
+    pub async fn clear() -> Result<(), CacheErr> {
+        let db = open().await?;
+        let stores = js_sys::Array::of2(&DATA_STORE.into(), &META_STORE.into());
+        let transaction = db.transaction_with_str_sequence_and_mode(&stores, IdbTransactionMode::Readwrite)?;
+        let clear_data = wait_for(&transaction.object_store(DATA_STORE)?.clear()?);
+        let clear_meta = wait_for(&transaction.object_store(META_STORE)?.clear()?);
+        clear_data.await?;
+        clear_meta.await?;
+        db.close();
+        Ok(())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod backend {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    impl From<std::io::Error> for CacheErr {
+        fn from(e: std::io::Error) -> CacheErr {
+            CacheErr{ code: 1, message: e.to_string() }
+        }
+    }
+
+    fn cache_dir() -> PathBuf {
+        let base = std::env::var_os("XDG_CACHE_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir);
+        base.join("kbo-gui")
+    }
+
+    pub async fn get(
+        key: &str,
+    ) -> Result<Option<Vec<u8>>, CacheErr> {
+        let path = cache_dir().join(key.to_string() + ".kbo");
+        if !path.exists() {
+            return Ok(None)
+        }
+        Ok(Some(std::fs::read(path)?))
+    }
+
+    pub async fn put(
+        entry: &CacheEntry,
+        bytes: &[u8],
+    ) -> Result<(), CacheErr> {
+        let dir = cache_dir();
+        std::fs::create_dir_all(&dir)?;
+        std::fs::write(dir.join(entry.key.clone() + ".kbo"), bytes)?;
+        std::fs::write(dir.join(entry.key.clone() + ".meta"), entry.file_name.clone() + "\n" + &entry.kmer_size.to_string())?;
+        Ok(())
+    }
+
+    pub async fn list() -> Result<Vec<CacheEntry>, CacheErr> {
+        let dir = cache_dir();
+        if !dir.exists() {
+            return Ok(Vec::new())
+        }
+        let mut entries: Vec<CacheEntry> = Vec::new();
+        for file in std::fs::read_dir(&dir)? {
+            let path = file?.path();
+            if path.extension() != Some(std::ffi::OsStr::new("kbo")) {
+                continue
+            }
+            let key = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+            let meta = std::fs::read_to_string(dir.join(key.clone() + ".meta")).unwrap_or_default();
+            let mut meta = meta.lines();
+            entries.push(CacheEntry {
+                file_name: meta.next().unwrap_or_default().to_string(),
+                kmer_size: meta.next().and_then(|k| k.parse::<u32>().ok()).unwrap_or_default(),
+                bytes: std::fs::metadata(&path)?.len() as usize,
+                key,
+            });
+        }
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        Ok(entries)
+    }
+
+    pub async fn evict(
+        key: &str,
+    ) -> Result<(), CacheErr> {
+        let dir = cache_dir();
+        std::fs::remove_file(dir.join(key.to_string() + ".kbo"))?;
+        let _ = std::fs::remove_file(dir.join(key.to_string() + ".meta"));
+        Ok(())
+    }
+
+    pub async fn clear() -> Result<(), CacheErr> {
+        let dir = cache_dir();
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        Ok(())
+    }
+}
@@ -11,9 +11,15 @@
 // the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
 // at your option.
 //
+use std::sync::Arc;
 
+use dioxus::prelude::*;
 use sbwt::LcsArray;
 use sbwt::SbwtIndexVariant;
+use serde::{Deserialize, Serialize};
+
+use crate::opts::{BuildOpts, GuiOpts};
+use crate::worker::{Job, JobOutput, Progress, WorkerErr};
 
 #[derive(Default, PartialEq)]
 pub enum KboMode {
@@ -23,36 +29,275 @@ pub enum KboMode {
     Map,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct ContigData {
     pub name: String,
+    #[serde(with = "serde_bytes")]
     pub seq: Vec<u8>,
+    // Phred+33 base qualities, empty unless read from FASTQ
+    #[serde(with = "serde_bytes")]
+    pub qual: Vec<u8>,
+    // Annotations, empty unless read from GenBank or EMBL
+    pub features: Vec<Feature>,
+    // Lowercase regions of the input as half-open intervals
+    pub soft_masked: Vec<(usize, usize)>,
+}
+
+/// Annotated feature with 1-based inclusive coordinates.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Feature {
+    pub kind: String,
+    pub start: usize,
+    pub end: usize,
+    pub strand: char,
+    pub gene: Option<String>,
+    pub locus_tag: Option<String>,
+    pub product: Option<String>,
 }
 
+// Index held by the worker. The SBWT and LCS array are shared between
+// clones so that passing indexes to the runners doesn't copy them.
+#[derive(Clone)]
 pub struct IndexData {
-    pub sbwt: SbwtIndexVariant,
-    pub lcs: LcsArray,
+    pub sbwt: Arc<SbwtIndexVariant>,
+    pub lcs: Arc<LcsArray>,
     pub file_name: String,
     pub bases: usize,
+    pub build_opts: BuildOpts,
+    pub build_time_ms: u64,
+    pub stats: IndexStats,
 }
 
-impl Clone for IndexData {
-    fn clone(&self) -> IndexData {
-        IndexData {
-            sbwt: match &self.sbwt {
-                SbwtIndexVariant::SubsetMatrix(index) => {
-                    sbwt::SbwtIndexVariant::SubsetMatrix(index.clone())
-                },
-            },
-            lcs: self.lcs.clone(),
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexStats {
+    pub n_kmers: usize,
+    pub n_sets: usize,
+    pub sbwt_bytes: usize,
+    pub lcs_bytes: usize,
+}
+
+impl IndexStats {
+    /// Sizing the SBWT walks the whole index, so this is only done when
+    /// the index is built or loaded.
+    pub fn new(sbwt_index: &SbwtIndexVariant, lcs: &LcsArray) -> IndexStats {
+        let SbwtIndexVariant::SubsetMatrix(sbwt) = sbwt_index;
+        // The serialized size is close to the size in memory
+        let sbwt_bytes = sbwt::write_sbwt_index_variant(sbwt_index, &mut std::io::sink()).unwrap_or(0);
+        IndexStats {
+            n_kmers: sbwt.n_kmers(),
+            n_sets: sbwt.n_sets(),
+            sbwt_bytes,
+            lcs_bytes: lcs.size_in_bytes(),
+        }
+    }
+}
+
+impl IndexData {
+    /// Metadata of the index stored at `pos` under cache key `key`.
+    pub fn info(&self, key: &str, pos: usize) -> IndexInfo {
+        IndexInfo {
+            key: key.to_string(),
+            pos,
             file_name: self.file_name.clone(),
             bases: self.bases,
+            build_opts: self.build_opts,
+            build_time_ms: self.build_time_ms,
+            stats: self.stats.clone(),
         }
     }
 }
 
-#[derive(Clone, Default, PartialEq)]
+/// Metadata of an index kept in the worker, which is all of the index
+/// that the UI thread sees. Jobs refer to the index by the cache key it
+/// is stored under and its position among the indexes stored there.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexInfo {
+    pub key: String,
+    pub pos: usize,
+    pub file_name: String,
+    pub bases: usize,
+    pub build_opts: BuildOpts,
+    pub build_time_ms: u64,
+    pub stats: IndexStats,
+}
+
+/// Results computed against each index in a set, so that when indexes
+/// are added or removed only the new ones need to be run.
+pub struct IndexResults<T> {
+    inputs: Option<(Vec<(u64, String)>, GuiOpts)>,
+    results: Vec<(IndexInfo, T)>,
+}
+
+impl<T> Default for IndexResults<T> {
+    fn default() -> IndexResults<T> {
+        IndexResults { inputs: None, results: Vec::new() }
+    }
+}
+
+impl<T> IndexResults<T> {
+    /// Drops all results if the other inputs of the runner changed.
+    pub fn reset_if_changed(&mut self, seq_data: &[SeqData], opts: GuiOpts) {
+        let inputs = (seq_data.iter().map(|x| (crate::cache::content_hash(x), x.label().to_string())).collect::<Vec<(u64, String)>>(), opts);
+        if self.inputs.as_ref() != Some(&inputs) {
+            self.inputs = Some(inputs);
+            self.results.clear();
+        }
+    }
+
+    /// Returns the result for `index` if it was computed earlier.
+    pub fn get(&self, index: &IndexInfo) -> Option<&T> {
+        self.results.iter().find(|(x, _)| x == index).map(|(_, res)| res)
+    }
+
+    /// Replaces the stored results, dropping those of removed indexes.
+    pub fn set(&mut self, results: Vec<(IndexInfo, T)>) {
+        self.results = results;
+    }
+}
+
+/// Runs `job` in the worker for each of `indexes` that has no result in
+/// `computed` for these `inputs` and `opts`, and returns the results of
+/// all indexes in order. `output` converts what the worker returned into
+/// the result for one index. Returns None if the job was cancelled.
+pub async fn run_per_index<'a, R: Clone, E>(
+    mut computed: Signal<IndexResults<Vec<R>>>,
+    inputs: &[SeqData],
+    indexes: &'a [IndexInfo],
+    opts: GuiOpts,
+    mut progress: Signal<Option<Progress>>,
+    job: impl Fn(&'a IndexInfo, GuiOpts) -> Job<'a>,
+    output: impl Fn(Result<JobOutput, WorkerErr>) -> Result<Vec<R>, E>,
+) -> Option<Result<Vec<R>, E>> {
+    computed.write().reset_if_changed(inputs, opts);
+    let mut results: Vec<(IndexInfo, Vec<R>)> = Vec::with_capacity(indexes.len());
+    for index in indexes.iter() {
+        if let Some(res) = computed.peek().get(index) {
+            results.push((index.clone(), res.clone()));
+            continue
+        }
+        // Use the options the index was built with
+        let submitted = crate::worker::submit(&job(index, GuiOpts { build_opts: index.build_opts, ..opts }), move |x| progress.set(Some(x)));
+        match submitted.await {
+            Err(e) if e.is_cancelled() => {
+                // Keep showing the previous results
+                progress.set(None);
+                return None
+            },
+            res => match output(res) {
+                Ok(res) => results.push((index.clone(), res)),
+                Err(e) => {
+                    progress.set(None);
+                    return Some(Err(e))
+                },
+            },
+        }
+    }
+    progress.set(None);
+
+    let res: Vec<R> = results.iter().flat_map(|(_, res)| res.iter().cloned()).collect();
+    computed.write().set(results);
+    Some(Ok(res))
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SeqData {
-    pub contigs: Vec<ContigData>,
+    // Shared so that copies of the inputs, like the staged and applied
+    // files or the selected reference contigs, don't copy the sequences
+    pub contigs: Vec<Arc<ContigData>>,
     pub file_name: String,
+    pub file_size: usize,
+    // Records dropped because their name isn't valid UTF-8
+    pub skipped_records: usize,
+    // Sample ID given in the sample sheet
+    pub sample: Option<String>,
+    // Records that don't look like nucleotide sequences
+    pub non_nucleotide: Vec<String>,
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub struct SeqQc {
+    pub n_contigs: usize,
+    pub total_length: usize,
+    pub n50: usize,
+    pub gc_content: f64,
+    pub non_acgt: usize,
+    pub empty_records: usize,
+    pub duplicate_names: usize,
+    pub skipped_records: usize,
+}
+
+impl SeqData {
+    /// Name shown in the results: the sample ID if there is one,
+    /// otherwise the file name.
+    pub fn label(&self) -> &str {
+        self.sample.as_deref().unwrap_or(&self.file_name)
+    }
+
+    pub fn qc(&self) -> SeqQc {
+        let mut lengths: Vec<usize> = self.contigs.iter().map(|contig| contig.seq.len()).collect();
+        let total_length: usize = lengths.iter().sum();
+
+        lengths.sort_unstable_by(|a, b| b.cmp(a));
+        let mut cumulative: usize = 0;
+        let n50 = lengths.iter().find(|len| {
+            cumulative += **len;
+            2 * cumulative >= total_length
+        }).copied().unwrap_or(0);
+
+        let mut gc: usize = 0;
+        let mut acgt: usize = 0;
+        self.contigs.iter().flat_map(|contig| contig.seq.iter()).for_each(|base| {
+            match base {
+                b'G' | b'C' => { gc += 1; acgt += 1 },
+                b'A' | b'T' => acgt += 1,
+                _ => (),
+            }
+        });
+
+        let mut names: Vec<&str> = self.contigs.iter().map(|contig| contig.name.as_str()).collect();
+        names.sort_unstable();
+        let duplicate_names = names.windows(2).filter(|x| x[0] == x[1]).count();
+
+        SeqQc {
+            n_contigs: self.contigs.len(),
+            total_length,
+            n50,
+            gc_content: if acgt > 0 { 100.0 * gc as f64 / acgt as f64 } else { 0.0 },
+            non_acgt: total_length - acgt,
+            empty_records: self.contigs.iter().filter(|contig| contig.seq.is_empty()).count(),
+            duplicate_names,
+            skipped_records: self.skipped_records,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seq_data_qc() {
+        let contig = |name: &str, seq: &[u8]| Arc::new(ContigData {
+            name: name.to_string(),
+            seq: seq.to_vec(),
+            qual: Vec::new(),
+            features: Vec::new(),
+            soft_masked: Vec::new(),
+        });
+        let seq_data = SeqData {
+            contigs: vec![contig("a", b"GGCCAA"), contig("b", b"ATNN"), contig("a", b"GCGCGCGCGC"), contig("c", b"")],
+            skipped_records: 2,
+            ..Default::default()
+        };
+        let got = seq_data.qc();
+        assert_eq!(got.n_contigs, 4);
+        assert_eq!(got.total_length, 20);
+        assert_eq!(got.n50, 10);
+        // 14 of the 18 A, C, G or T bases are G or C
+        assert!((got.gc_content - 100.0 * 14.0 / 18.0).abs() < 1e-9);
+        assert_eq!(got.non_acgt, 2);
+        assert_eq!(got.empty_records, 1);
+        assert_eq!(got.duplicate_names, 1);
+        assert_eq!(got.skipped_records, 2);
+    }
 }
@@ -0,0 +1,121 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+use std::borrow::Cow;
+
+use dioxus::prelude::*;
+
+use crate::cache::*;
+use crate::common::*;
+use crate::worker::{Job, JobOutput};
+
+// The worker keeps the indexes in memory, so the cache is only changed there
+async fn list_entries() -> Result<Vec<CacheEntry>, CacheErr> {
+    match crate::worker::submit(&Job::ListCache, |_| {}).await {
+        Ok(JobOutput::ListCache(res)) => res,
+        Ok(_) => Err(CacheErr{ code: 1, message: "Unexpected result from worker.".to_string() }),
+        Err(e) => Err(CacheErr{ code: 1, message: e.message }),
+    }
+}
+
+async fn update_entries(job: Job<'_>) -> Result<(), CacheErr> {
+    match crate::worker::submit(&job, |_| {}).await {
+        Ok(JobOutput::UpdateCache(res)) => res,
+        Ok(_) => Err(CacheErr{ code: 1, message: "Unexpected result from worker.".to_string() }),
+        Err(e) => Err(CacheErr{ code: 1, message: e.message }),
+    }
+}
+
+#[component]
+pub fn IndexCacheManager(
+    cached_index: ReadOnlySignal<Vec<IndexInfo>>,
+) -> Element {
+    let mut refresh: Signal<usize> = use_signal(|| 0);
+    let mut error: Signal<String> = use_signal(String::new);
+
+    let entries = use_resource(move || async move {
+        // Re-list when an index is built or an entry is removed
+        let _ = cached_index.read();
+        let _ = refresh.read();
+        list_entries().await
+    });
+
+    let rows = match &*entries.read() {
+        Some(Ok(data)) => data.clone(),
+        Some(Err(e)) => return rsx! { { "Error: ".to_string() + &e.message } },
+        None => return rsx! { span { class: "loader" } },
+    };
+
+    rsx! {
+        if rows.is_empty() {
+            div { class: "row", "No cached indexes." }
+        } else {
+            table {
+                thead {
+                    tr {
+                        th { "file" }
+                        th { "k" }
+                        th { "size (MB)" }
+                        th { "" }
+                    }
+                }
+                tbody {
+                    {
+                        rows.into_iter().map(|entry| {
+                            let size = format!("{:.2}", entry.bytes as f64 / 1e6);
+                            rsx! {
+                                tr {
+                                    td { "{entry.file_name}" }
+                                    td { "{entry.kmer_size}" }
+                                    td { "{size}" }
+                                    td {
+                                        input {
+                                            r#type: "button",
+                                            value: "Evict",
+                                            onclick: move |_| {
+                                                let key = entry.key.clone();
+                                                async move {
+                                                    if let Err(e) = update_entries(Job::EvictCache { key: Cow::Borrowed(&key) }).await {
+                                                        error.set("Error: ".to_string() + &e.message);
+                                                    }
+                                                    *refresh.write() += 1;
+                                                }
+                                            },
+                                        }
+                                    }
+                                }
+                            }
+                        })
+                    }
+                }
+            }
+            div { class: "row",
+                  input {
+                      r#type: "button",
+                      value: "Clear cache",
+                      onclick: move |_| {
+                          async move {
+                              if let Err(e) = update_entries(Job::ClearCache).await {
+                                  error.set("Error: ".to_string() + &e.message);
+                              }
+                              *refresh.write() += 1;
+                          }
+                      },
+                  }
+            }
+        }
+        div { class: "row",
+              { (*error.read()).clone() },
+        },
+    }
+}
@@ -14,10 +14,14 @@
 use crate::common::*;
 use crate::dioxus_sortable::*;
 use crate::opts::GuiOpts;
+use crate::worker::{Job, JobOutput, Progress};
+
+use std::borrow::Cow;
 
 use chrono::offset::Local;
 use dioxus::prelude::*;
 use kbo::variant_calling::Variant;
+use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 enum CallResultField {
@@ -31,7 +35,7 @@ enum CallResultField {
     Filter,
     Info,
     Format,
-    Unknown,
+    Genotype,
 }
 
 impl PartialOrdBy<CallResult> for CallResultField {
@@ -46,7 +50,7 @@ impl PartialOrdBy<CallResult> for CallResultField {
             CallResultField::Filter => a.filter.partial_cmp(&b.filter),
             CallResultField::Info => a.info.partial_cmp(&b.info),
             CallResultField::Format => a.format.partial_cmp(&b.format),
-            CallResultField::Unknown => a.unknown.partial_cmp(&b.unknown),
+            CallResultField::Genotype => a.genotype.partial_cmp(&b.genotype),
         }
     }
 }
@@ -62,7 +66,7 @@ impl Sortable for CallResultField {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CallResult {
     chromosome: String,
     position: u64,
@@ -73,14 +77,16 @@ pub struct CallResult {
     filter: String,
     info: String,
     format: String,
-    unknown: String,
+    // Genotype of the sample, the query the index was built from
+    genotype: String,
 }
 
-#[derive(Clone, Default, Debug, PartialEq)]
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct CallResults {
     calls: Vec<CallResult>,
     contig_info: Vec<(String, usize)>,
     ref_file: String,
+    sample: String,
 }
 
 #[component]
@@ -103,7 +109,7 @@ pub fn SortableCallResultTable(
                     Th { sorter: sorter, field: CallResultField::Filter, "FILTER" }
                     Th { sorter: sorter, field: CallResultField::Info, "INFO" }
                     Th { sorter: sorter, field: CallResultField::Format, "FORMAT" }
-                    Th { sorter: sorter, field: CallResultField::Unknown, "unknown" }
+                    Th { sorter: sorter, field: CallResultField::Genotype, "{data.sample}" }
                 }
             }
             tbody {
@@ -120,7 +126,7 @@ pub fn SortableCallResultTable(
                                 td { "{row.filter}" }
                                 td { "{row.info}" }
                                 td { "{row.format}" }
-                                td { "{row.unknown}" }
+                                td { "{row.genotype}" }
                             }
                         }
                     })
@@ -135,7 +141,7 @@ fn CopyableCallResultTable(
     data: CallResults,
 ) -> Element {
 
-    let display = format_call_header(&data.ref_file, &data.contig_info) +
+    let display = format_call_header(&data.ref_file, &data.sample, &data.contig_info) +
         &data.calls.iter().map(|x| {
         x.chromosome.clone() + "\t" +
             &x.position.to_string() + "\t" +
@@ -146,7 +152,7 @@ fn CopyableCallResultTable(
             &x.filter.to_string() + "\t" +
             &x.info.to_string() + "\t" +
             &x.format.clone() + "\t" +
-            &x.unknown.clone() + "\n"
+            &x.genotype.clone() + "\n"
     }).collect::<String>();
 
     rsx! {
@@ -227,13 +233,14 @@ fn format_call_result(
         filter: ".".to_string(),
         info,
         format: "GT".to_string(),
-        unknown: "1".to_string(),
+        genotype: "1".to_string(),
     }
 
 }
 
 fn format_call_header(
     ref_file: &str,
+    sample: &str,
     contig_info: &[(String, usize)],
 ) -> String {
     let current_date = Local::now().format("%Y%m%d").to_string();
@@ -247,7 +254,9 @@ fn format_call_header(
         "##fileDate=" + &current_date.to_string() + "\n" +
         "##source=kbo-gui v" + env!("CARGO_PKG_VERSION") + "\n" +
         "##reference=" + ref_file + "\n" +
-        "##phasing=none\n"
+        "##SAMPLE=<ID=" + sample + ">\n" +
+        "##phasing=none\n" +
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\t" + sample + "\n"
 }
 
 #[component]
@@ -277,17 +286,18 @@ pub fn CallOptsSelector(
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct CallRunnerErr {
     pub code: usize,
     pub message: String,
 }
 
-async fn call_runner(
+pub fn call_runner(
     reference: &[SeqData],
     index: &IndexData,
     call_opts: kbo::CallOpts,
-) -> Result<CallResults, CallRunnerErr>{
+    progress: &mut dyn FnMut(Progress),
+) -> Result<Vec<CallResults>, CallRunnerErr>{
 
     if reference.is_empty() {
         return Err(CallRunnerErr{ code: 2, message: "Argument `reference` is empty.".to_string() })
@@ -296,31 +306,37 @@ async fn call_runner(
         return Err(CallRunnerErr{ code: 3, message: "Argument `queries` is empty.".to_string() })
     }
 
-    let ref_contigs = reference.first().unwrap();
-    let mut contig_info: Vec<(String, usize)> = Vec::with_capacity(ref_contigs.contigs.len());
-    let mut res: Vec<CallResult> = Vec::new();
-
-    ref_contigs.contigs.iter().for_each(|contig| {
-        let mut header_contents = contig.name.split_whitespace();
-        let contig_name = header_contents.next().expect("Contig name");
-        contig_info.push((contig.name.clone(), contig.seq.len()));
-        let variants = kbo::call(&index.sbwt, &index.lcs, &contig.seq, call_opts.clone());
-
-        res.extend(variants.iter().flat_map(|variant| {
-
-            let flanking = split_flanking_variants(&variant.ref_chars, &variant.query_chars, variant.query_pos);
-            if flanking.is_some() {
-                let (var1, var2) = flanking.unwrap();
-                let record1 = format_call_result(&var1, &contig.seq, contig_name);
-                let record2 = format_call_result(&var2, &contig.seq, contig_name);
-                vec![record1, record2]
-            } else {
-                vec![format_call_result(variant, &contig.seq, contig_name)]
-            }
-        }));
-    });
-    if !res.is_empty() {
-        Ok(CallResults { calls: res, contig_info, ref_file: reference[0].file_name.clone() })
+    let n_contigs: usize = reference.iter().map(|ref_contigs| ref_contigs.contigs.len()).sum();
+    let mut step = 0;
+    let res: Vec<CallResults> = reference.iter().map(|ref_contigs| {
+        let mut contig_info: Vec<(String, usize)> = Vec::with_capacity(ref_contigs.contigs.len());
+        let mut calls: Vec<CallResult> = Vec::new();
+        ref_contigs.contigs.iter().for_each(|contig| {
+            step += 1;
+            progress(Progress::new("Calling variants in contig", step, n_contigs));
+            let mut header_contents = contig.name.split_whitespace();
+            let contig_name = header_contents.next().expect("Contig name");
+            contig_info.push((contig.name.clone(), contig.seq.len()));
+            let variants = kbo::call(&index.sbwt, &index.lcs, &contig.seq, call_opts.clone());
+
+            calls.extend(variants.iter().flat_map(|variant| {
+
+                let flanking = split_flanking_variants(&variant.ref_chars, &variant.query_chars, variant.query_pos);
+                if let Some((var1, var2)) = flanking {
+                    let record1 = format_call_result(&var1, &contig.seq, contig_name);
+                    let record2 = format_call_result(&var2, &contig.seq, contig_name);
+                    vec![record1, record2]
+                } else {
+                    vec![format_call_result(variant, &contig.seq, contig_name)]
+                }
+            }));
+        });
+        CallResults { calls, contig_info, ref_file: ref_contigs.label().to_string(), sample: index.file_name.clone() }
+    }).collect();
+
+    // One VCF per reference, including references without variants
+    if res.iter().any(|x| !x.calls.is_empty()) {
+        Ok(res)
     } else {
         Err(CallRunnerErr{ code: 0, message: "No variants detected.".to_string() })
     }
@@ -329,9 +345,10 @@ async fn call_runner(
 #[component]
 pub fn Call(
     ref_contigs: ReadOnlySignal<Vec<SeqData>>,
-    index: ReadOnlySignal<Vec<IndexData>>,
+    index: ReadOnlySignal<Vec<IndexInfo>>,
     opts: ReadOnlySignal<GuiOpts>,
-    result: Signal<Result<CallResults, CallRunnerErr>>,
+    result: Signal<Result<Vec<CallResults>, CallRunnerErr>>,
+    progress: Signal<Option<Progress>>,
 ) -> Element {
 
     if ref_contigs.read().is_empty() {
@@ -343,7 +360,23 @@ pub fn Call(
 
     let _ = use_resource(move || {
         async move {
-            let variants = call_runner(&ref_contigs.read(), index.read().first().unwrap(), opts.read().to_kbo_call()).await;
+            let job = crate::worker::submit(&Job::Call {
+                reference: Cow::Borrowed(&ref_contigs.read()),
+                index: Cow::Borrowed(index.read().first().unwrap()),
+                // Use the options the index was built with
+                opts: GuiOpts { build_opts: index.read()[0].build_opts, ..*opts.read() },
+            }, move |x| progress.set(Some(x)));
+            let variants = match job.await {
+                Ok(JobOutput::Call(res)) => res,
+                Ok(_) => Err(CallRunnerErr{ code: 0, message: "Unexpected result from worker.".to_string() }),
+                Err(e) if e.is_cancelled() => {
+                    // Keep showing the previous results
+                    progress.set(None);
+                    return
+                },
+                Err(e) => Err(CallRunnerErr{ code: 0, message: e.message }),
+            };
+            progress.set(None);
             result.set(variants);
         }
     }).suspend()?;
@@ -353,16 +386,21 @@ pub fn Call(
 
 #[component]
 pub fn CallRenderer(
-    result: ReadOnlySignal<Result<CallResults, CallRunnerErr>>,
+    result: ReadOnlySignal<Result<Vec<CallResults>, CallRunnerErr>>,
     opts: ReadOnlySignal<GuiOpts>,
 ) -> Element {
     match &*result.read() {
         Ok(res) => {
             rsx! {
-                if opts.read().out_opts.interactive {
-                    SortableCallResultTable { data: res.clone() }
-                } else {
-                    CopyableCallResultTable { data: res.clone() }
+                for data in res.iter() {
+                    div { class: "row",
+                          strong { { data.ref_file.clone() } },
+                    }
+                    if opts.read().out_opts.interactive {
+                        SortableCallResultTable { data: data.clone() }
+                    } else {
+                        CopyableCallResultTable { data: data.clone() }
+                    }
                 }
             }
         },
@@ -11,11 +11,18 @@
 // the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
 // at your option.
 //
+use std::borrow::Cow;
+
 use dioxus::prelude::*;
+use dioxus::html::{FileEngine, HasFileData};
+use needletail::errors::ParseError;
 
 use crate::common::*;
-use crate::opts::GuiOpts;
+use crate::components::stats::SequenceQc;
+use crate::opts::{BuildOpts, GuiOpts};
+use crate::samplesheet::{read_sample_sheet, SampleSheet};
 use crate::util::build_indexes;
+use crate::worker::{Job, JobOutput, Progress};
 
 #[component]
 pub fn BuildOptsSelector(
@@ -77,6 +84,169 @@ pub fn BuildOptsSelector(
                   },
               }
         }
+        div { class: "row-contents",
+              div { class: "column-right",
+                    "Memory budget (MB)",
+              }
+              div { class: "column-left",
+                  input {
+                      r#type: "number",
+                      id: "max_memory_mb",
+                      name: "max_memory_mb",
+                      min: "1",
+                      value: opts.read().build_opts.max_memory_mb.to_string(),
+                      onchange: move |event| {
+                          let new = event.value().parse::<u64>();
+                          if let Ok(new_max) = new { opts.write().build_opts.max_memory_mb = new_max.max(1) };
+                      }
+                  },
+              }
+        }
+        div { class: "row-contents",
+              div { class: "column-right",
+                    "Min base quality",
+              }
+              div { class: "column-left",
+                  input {
+                      r#type: "number",
+                      id: "min_base_quality",
+                      name: "min_base_quality",
+                      min: "0",
+                      max: "93",
+                      value: opts.read().build_opts.min_base_quality.to_string(),
+                      onchange: move |event| {
+                          let new = event.value().parse::<u8>();
+                          if let Ok(new_qual) = new { opts.write().build_opts.min_base_quality = new_qual.min(93) };
+                      }
+                  },
+              }
+        }
+        div { class: "row-contents",
+              div { class: "column-right",
+                    "Min k-mer count",
+              }
+              div { class: "column-left",
+                  input {
+                      r#type: "number",
+                      id: "min_kmer_count",
+                      name: "min_kmer_count",
+                      min: "1",
+                      max: "255",
+                      value: opts.read().build_opts.min_kmer_count.to_string(),
+                      onchange: move |event| {
+                          let new = event.value().parse::<u32>();
+                          if let Ok(new_count) = new { opts.write().build_opts.min_kmer_count = new_count.clamp(1, 255) };
+                      }
+                  },
+              }
+        }
+        div { class: "row-contents",
+              div { class: "column",
+                    "Exclude soft-masked",
+              }
+              div { class: "column-left",
+                  input {
+                      r#type: "checkbox",
+                      name: "exclude_soft_masked",
+                      id: "exclude_soft_masked",
+                      checked: opts.read().build_opts.exclude_soft_masked,
+                      onchange: move |_| {
+                          let old: bool = opts.read().build_opts.exclude_soft_masked;
+                          opts.write().build_opts.exclude_soft_masked = !old;
+                      }
+                  },
+              }
+        }
+        div { class: "row-contents",
+              div { class: "column-right",
+                    "Min contig length",
+              }
+              div { class: "column-left",
+                  input {
+                      r#type: "number",
+                      id: "min_contig_len",
+                      name: "min_contig_len",
+                      min: "0",
+                      value: opts.read().build_opts.min_contig_len.to_string(),
+                      onchange: move |event| {
+                          let new = event.value().parse::<u64>();
+                          if let Ok(new_len) = new { opts.write().build_opts.min_contig_len = new_len };
+                      }
+                  },
+              }
+        }
+    }
+}
+
+#[component]
+pub fn ContigSelector(
+    seq_data: ReadOnlySignal<Vec<SeqData>>,
+    excluded: Signal<Vec<(String, String)>>,
+) -> Element {
+    if seq_data.read().is_empty() {
+        return rsx! { { "".to_string() } }
+    }
+
+    rsx! {
+        div { class: "row",
+              input {
+                  r#type: "button",
+                  value: "Select all",
+                  onclick: move |_| excluded.write().clear(),
+              }
+              input {
+                  r#type: "button",
+                  value: "Select none",
+                  onclick: move |_| {
+                      let all = seq_data.read().iter().flat_map(|file| {
+                          file.contigs.iter().map(|contig| (file.file_name.clone(), contig.name.clone()))
+                      }).collect::<Vec<(String, String)>>();
+                      excluded.set(all);
+                  },
+              }
+        }
+        table {
+            thead {
+                tr {
+                    th { "" }
+                    th { "file" }
+                    th { "contig" }
+                    th { "length" }
+                }
+            }
+            tbody {
+                {
+                    seq_data.read().iter().flat_map(|file| {
+                        file.contigs.iter().map(|contig| {
+                            let key = (file.file_name.clone(), contig.name.clone());
+                            let checked = !excluded.read().contains(&key);
+                            let length = contig.seq.len();
+                            rsx! {
+                                tr {
+                                    td {
+                                        input {
+                                            r#type: "checkbox",
+                                            checked: checked,
+                                            onchange: move |_| {
+                                                let mut excluded = excluded.write();
+                                                if let Some(pos) = excluded.iter().position(|x| *x == key) {
+                                                    excluded.remove(pos);
+                                                } else {
+                                                    excluded.push(key.clone());
+                                                }
+                                            },
+                                        }
+                                    }
+                                    td { "{file.file_name}" }
+                                    td { "{contig.name}" }
+                                    td { "{length}" }
+                                }
+                            }
+                        }).collect::<Vec<Element>>()
+                    })
+                }
+            }
+        }
     }
 }
 
@@ -104,45 +274,205 @@ pub fn DetailSwitcher(
     }
 }
 
+// Lists at most the first few `names`
+fn list_names(names: &[String]) -> String {
+    const MAX_LISTED: usize = 5;
+    let mut list = names.iter().take(MAX_LISTED).cloned().collect::<Vec<String>>().join(", ");
+    if names.len() > MAX_LISTED {
+        list += &(" and ".to_string() + &(names.len() - MAX_LISTED).to_string() + " more");
+    }
+    list
+}
+
+// Sets the sample IDs of `data` from `sample_sheet`, writing to `data`
+// only if a label changes.
+fn label_samples(
+    sample_sheet: &Option<SampleSheet>,
+    mut data: Signal<Vec<SeqData>>,
+) {
+    let labels = match sample_sheet {
+        Some(sheet) => sheet.labels(&data.read()),
+        None => vec![None; data.read().len()],
+    };
+    if data.read().iter().zip(labels.iter()).any(|(x, label)| x.sample != *label) {
+        data.write().iter_mut().zip(labels).for_each(|(x, label)| x.sample = label);
+    }
+}
+
+// Adds `new` to the staged files, replacing files with the same name,
+// and returns the errors and warnings about its alphabet.
+fn stage_files(
+    mut out_data: Signal<Vec<SeqData>>,
+    multiple: bool,
+    mut new: Vec<SeqData>,
+    sample_sheet: &Option<SampleSheet>,
+) -> String {
+    if let Some(sheet) = sample_sheet {
+        new.iter_mut().for_each(|x| x.sample = sheet.sample(&x.file_name).map(|sample| sample.to_string()));
+    }
+    // Files with only protein or other non-nucleotide records are rejected
+    let (new, rejected): (Vec<SeqData>, Vec<SeqData>) = new.into_iter().partition(|x| {
+        x.contigs.is_empty() || x.non_nucleotide.len() < x.contigs.len()
+    });
+    let mut messages: Vec<String> = Vec::new();
+    if !rejected.is_empty() {
+        let files = rejected.iter().map(|x| x.file_name.clone()).collect::<Vec<String>>();
+        messages.push("Error: not nucleotide sequences, possibly protein: ".to_string() + &list_names(&files) + ".");
+    }
+    new.iter().filter(|x| !x.non_nucleotide.is_empty()).for_each(|x| {
+        messages.push("Warning: ".to_string() + &x.file_name + " has records that don't look like nucleotide sequences: " + &list_names(&x.non_nucleotide) + ".");
+    });
+
+    if !multiple {
+        if !new.is_empty() {
+            out_data.set(new.into_iter().take(1).collect());
+        }
+        return messages.join(" ")
+    }
+    let mut staged = out_data.write();
+    for seq_data in new {
+        match staged.iter().position(|x| x.file_name == seq_data.file_name) {
+            Some(pos) => staged[pos] = seq_data,
+            None => staged.push(seq_data),
+        }
+    }
+    messages.join(" ")
+}
+
+// Size of the pieces that input files are read and parsed in
+const READ_CHUNK_SIZE: usize = 1 << 22;
+
+// Extensions of the accepted sequence files
+fn sequence_extensions(
+    reads: bool,
+    annotated: bool,
+) -> Vec<String> {
+    // Protein files (.faa, .mpfa) can't be aligned against nucleotides
+    let fasta = [".fasta", ".fas", ".fa", ".fna", ".ffn", ".frn"];
+    let fastq = [".fastq", ".fq"];
+    let flat_file = [".gbk", ".gb", ".gbff", ".embl"];
+    let compression = ["", ".gz", ".bz2", ".xz", ".zst"];
+    fasta.iter()
+        .chain(if reads { fastq.iter() } else { [].iter() })
+        .chain(if annotated { flat_file.iter() } else { [].iter() })
+        .flat_map(|ext| compression.iter().map(move |suffix| ext.to_string() + suffix))
+        .collect::<Vec<String>>()
+}
+
+async fn read_file_engine(
+    file_engine: &std::sync::Arc<dyn FileEngine>,
+    extensions: &[String],
+) -> Result<Vec<SeqData>, ParseError> {
+    let mut seq_data: Vec<SeqData> = Vec::new();
+    for file_name in &file_engine.files() {
+        if crate::archive::is_archive(file_name) {
+            // Archives are read whole to find the files inside
+            let contents = file_engine.read_file(file_name).await
+                .ok_or(std::io::Error::new(std::io::ErrorKind::NotFound, "Couldn't open ".to_string() + file_name))?;
+            let keep = |name: &str| extensions.iter().any(|ext| name.ends_with(ext.as_str()));
+            let mut files = crate::archive::read_archive(&contents, &keep)?;
+            if files.is_empty() {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Archive ".to_string() + file_name + " contains no sequence files.").into())
+            }
+            seq_data.append(&mut files);
+            continue
+        }
+        let mut reader = crate::reader::SeqReader::default();
+        crate::browser::read_file_chunks(file_engine, file_name, READ_CHUNK_SIZE, |chunk| reader.push(chunk)).await?;
+        seq_data.push(reader.finish(file_name)?);
+    }
+    Ok(seq_data)
+}
+
 #[component]
 pub fn FastaFileSelector(
     multiple: bool,
+    reads: bool,
+    annotated: bool,
     out_data: Signal<Vec<SeqData>>,
+    sample_sheet: ReadOnlySignal<Option<SampleSheet>>,
 ) -> Element {
     let mut error: Signal<String> = use_signal(String::new);
+    let mut pasted: Signal<String> = use_signal(String::new);
+
+    // Archives of many files are only accepted when several can be selected
+    let accept = sequence_extensions(reads, annotated).into_iter()
+        .chain(crate::archive::ARCHIVE_EXTENSIONS.iter().filter(|_| multiple).map(|ext| ext.to_string()))
+        .collect::<Vec<String>>()
+        .join(",");
 
     rsx! {
         div { class: "row",
+              ondragover: move |evt| evt.prevent_default(),
+              ondrop: move |evt| {
+                  evt.prevent_default();
+                  error.set(String::new());
+                  async move {
+                      if let Some(file_engine) = &evt.files() {
+                          match read_file_engine(file_engine, &sequence_extensions(reads, annotated)).await {
+                              Ok(seq_data) => error.set(stage_files(out_data, multiple, seq_data, &sample_sheet.read())),
+                              Err(e) => error.set("Error: ".to_string() + &e.msg),
+                          }
+                      }
+                  }
+              },
               input {
                   // tell the input to pick a file
                   r#type: "file",
                   // list the accepted extensions
-                  accept: ".fasta,.fas,.fa,.fna,.ffn,.faa,.mpfa,.frn,.fasta.gz,.fas.gz,.fa.gz,.fna.gz,.ffn.gz,.faa.gz,.mpfa.gz,.frn.gz",
+                  accept: accept,
                   // pick multiple files
                   multiple: multiple,
                   onchange: move |evt| {
                       error.set(String::new());
                       async move {
                           if let Some(file_engine) = &evt.files() {
-                              let files = file_engine.files();
-                              let mut seq_data: Vec<(String, Vec<u8>)> = Vec::new();
-                              for file_name in &files {
-                                  if let Some(file) = file_engine.read_file(file_name).await
-                                  {
-                                      seq_data.push((file_name.clone(), file));
-                                  }
-                              }
-
-                              let ref_contigs = crate::util::read_fasta_files(&seq_data).await;
-
-                              match &ref_contigs {
-                                  Ok(ref_data) => out_data.set(ref_data.clone()),
+                              match read_file_engine(file_engine, &sequence_extensions(reads, annotated)).await {
+                                  Ok(seq_data) => error.set(stage_files(out_data, multiple, seq_data, &sample_sheet.read())),
                                   Err(e) => error.set("Error: ".to_string() + &e.msg),
                               }
                           }
                       }
                   },
               }
+              { if multiple { " or drop files here" } else { " or drop a file here" } },
+        },
+        div { class: "row",
+              details {
+                  summary { "Paste sequence" },
+                  textarea {
+                      rows: "4",
+                      cols: "40",
+                      placeholder: "FASTA records or a bare sequence",
+                      value: pasted.read().clone(),
+                      oninput: move |event| pasted.set(event.value()),
+                  },
+                  br {},
+                  input {
+                      r#type: "button",
+                      value: "Use pasted sequence",
+                      onclick: move |_| {
+                          error.set(String::new());
+                          async move {
+                              let text = pasted.read().clone();
+                              match crate::util::read_pasted_sequence(&text).await {
+                                  Ok(seq_data) if seq_data.contigs.is_empty() => error.set("Error: no sequence was pasted.".to_string()),
+                                  Ok(seq_data) => error.set(stage_files(out_data, multiple, vec![seq_data], &sample_sheet.read())),
+                                  Err(e) => error.set("Error: ".to_string() + &e.msg),
+                              }
+                          }
+                      },
+                  },
+              }
+        },
+        StagedFileList { out_data },
+        if !out_data.read().is_empty() {
+            div { class: "row",
+                  details {
+                      summary { "QC summary" },
+                      SequenceQc { seq_data: out_data },
+                  }
+            }
         },
         div { class: "row",
               { (*error.read()).clone() },
@@ -153,8 +483,10 @@ pub fn FastaFileSelector(
 #[component]
 pub fn IndexBuilder(
     seq_data: ReadOnlySignal<Vec<SeqData>>,
-    gui_opts: ReadOnlySignal<GuiOpts>,
-    cached_index: Signal<Vec<IndexData>>,
+    build_opts: ReadOnlySignal<BuildOpts>,
+    detailed: ReadOnlySignal<bool>,
+    cached_index: Signal<Vec<IndexInfo>>,
+    progress: Signal<Option<Progress>>,
 ) -> Element {
 
   if seq_data.is_empty() {
@@ -162,26 +494,127 @@ pub fn IndexBuilder(
   }
 
   let indexes = use_resource(move || async move {
-        // Delay start to render a loading spinner
-        let mut indexes: Vec<IndexData> = Vec::new();
-        if gui_opts.read().out_opts.detailed {
-            let tmp = crate::util::build_runner(&seq_data.read(), gui_opts.read().build_opts.to_kbo(), true).await;
-            if let Ok(mut data) = tmp {
-                indexes.append(&mut data);
-            }
+        // Copy the inputs so no signal is borrowed while the worker runs
+        let seq_data = seq_data.read().clone();
+        let build_opts = *build_opts.read();
+        let on_progress = move |x: Progress| progress.set(Some(x));
+        let res = if *detailed.read() {
+            crate::util::build_runner(&seq_data, build_opts, true, on_progress).await
         } else {
-            let mut tmp = build_indexes(&seq_data.read(), gui_opts.read().build_opts.to_kbo()).await;
-            indexes.append(&mut tmp);
-        }
-        indexes
+            build_indexes(&seq_data, build_opts, on_progress).await
+        };
+        progress.set(None);
+        res
     }).suspend()?;
 
+    let error: String = match &*indexes.read() {
+        Err(e) if e.code != 3 => "Error: ".to_string() + &e.message,
+        _ => String::new(),
+    };
+
     use_effect(move || {
-        cached_index.set(indexes.read().clone());
+        // Keep the previous index if building failed or was cancelled
+        if let Ok(data) = &*indexes.read() {
+            cached_index.set(data.clone());
+        }
     });
 
     rsx! {
-        { "".to_string() },
+        { error },
+    }
+}
+
+#[component]
+pub fn IndexDownloader(
+    index: ReadOnlySignal<Vec<IndexInfo>>,
+) -> Element {
+    let mut error: Signal<String> = use_signal(String::new);
+
+    if index.read().is_empty() {
+        return rsx! { { "".to_string() } }
+    }
+
+    rsx! {
+        div { class: "row",
+              input {
+                  r#type: "button",
+                  value: "Download index",
+                  onclick: move |_| async move {
+                      error.set(String::new());
+                      // The indexes are kept in the worker, which writes the file
+                      let indexes = index.read().clone();
+                      let file_name = if indexes.len() == 1 { indexes[0].file_name.clone() } else { "kbo-index".to_string() } + ".kbo";
+                      let res = match crate::worker::submit(&Job::Export { indexes: Cow::Borrowed(&indexes) }, |_| {}).await {
+                          Ok(JobOutput::Export(Ok(bytes))) => crate::browser::download_bytes(&file_name, &bytes).map_err(|e| e.message),
+                          Ok(JobOutput::Export(Err(e))) => Err(e.message),
+                          Ok(_) => Err("Unexpected result from worker.".to_string()),
+                          Err(e) => Err(e.message),
+                      };
+                      if let Err(e) = res {
+                          error.set("Error: ".to_string() + &e);
+                      }
+                  },
+              }
+        },
+        div { class: "row",
+              { (*error.read()).clone() },
+        },
+    }
+}
+
+#[component]
+pub fn IndexFileSelector(
+    staged_seq_data: Signal<Vec<SeqData>>,
+    seq_data: Signal<Vec<SeqData>>,
+    gui_opts: Signal<GuiOpts>,
+    run_opts: Signal<GuiOpts>,
+    cached_index: Signal<Vec<IndexInfo>>,
+) -> Element {
+    let mut error: Signal<String> = use_signal(String::new);
+
+    rsx! {
+        div { class: "row",
+              input {
+                  r#type: "file",
+                  accept: ".kbo",
+                  multiple: false,
+                  onchange: move |evt| {
+                      error.set(String::new());
+                      async move {
+                          if let Some(file_engine) = &evt.files() {
+                              let files = file_engine.files();
+                              if let Some(file_name) = files.first() {
+                                  if let Some(file) = file_engine.read_file(file_name).await {
+                                      let res = match crate::worker::submit(&Job::Load { contents: Cow::Borrowed(&file) }, |_| {}).await {
+                                          Ok(JobOutput::Load(res)) => res.map_err(|e| e.message),
+                                          Ok(_) => Err("Unexpected result from worker.".to_string()),
+                                          Err(e) => Err(e.message),
+                                      };
+                                      match res {
+                                          Ok(indexes) => {
+                                              // Clear the query files so IndexBuilder
+                                              // doesn't overwrite the loaded index.
+                                              staged_seq_data.set(Vec::new());
+                                              seq_data.set(Vec::new());
+                                              // Index files don't store the memory budget
+                                              let max_memory_mb = gui_opts.read().build_opts.max_memory_mb;
+                                              let build_opts = BuildOpts { max_memory_mb, ..indexes[0].build_opts };
+                                              gui_opts.write().build_opts = build_opts;
+                                              run_opts.write().build_opts = build_opts;
+                                              cached_index.set(indexes);
+                                          },
+                                          Err(e) => error.set("Error: ".to_string() + &e),
+                                      }
+                                  }
+                              }
+                          }
+                      }
+                  },
+              }
+        },
+        div { class: "row",
+              { (*error.read()).clone() },
+        },
     }
 }
 
@@ -209,6 +642,78 @@ pub fn InteractivitySwitcher(
     }
 }
 
+#[component]
+pub fn JobStatus(
+    progress: Signal<Option<Progress>>,
+    reference: Signal<Vec<SeqData>>,
+    queries: Signal<Vec<SeqData>>,
+) -> Element {
+    rsx! {
+        div { class: "row",
+              span { class: "loader" },
+              if let Some(status) = &*progress.read() {
+                  { " ".to_string() + &status.message + " " + &status.step.to_string() + "/" + &status.total.to_string() }
+              }
+        }
+        div { class: "row",
+              input {
+                  r#type: "button",
+                  value: "Cancel",
+                  onclick: move |_| {
+                      crate::worker::cancel();
+                      progress.set(None);
+                      // Unapply the inputs so that the Run button can retry
+                      reference.set(Vec::new());
+                      queries.set(Vec::new());
+                  },
+              }
+        }
+    }
+}
+
+#[component]
+pub fn RunButton(
+    staged_opts: ReadOnlySignal<GuiOpts>,
+    run_opts: Signal<GuiOpts>,
+    staged_reference: ReadOnlySignal<Vec<SeqData>>,
+    reference: Signal<Vec<SeqData>>,
+    staged_queries: ReadOnlySignal<Vec<SeqData>>,
+    queries: Signal<Vec<SeqData>>,
+    staged_excluded: ReadOnlySignal<Vec<(String, String)>>,
+    excluded: Signal<Vec<(String, String)>>,
+) -> Element {
+    let pending = *staged_opts.read() != *run_opts.read() ||
+        *staged_reference.read() != *reference.read() ||
+        *staged_queries.read() != *queries.read() ||
+        *staged_excluded.read() != *excluded.read();
+
+    rsx! {
+        input {
+            r#type: "button",
+            value: "Run",
+            disabled: !pending,
+            onclick: move |_| {
+                // Only write the inputs that changed so unchanged ones don't rerun
+                if *staged_reference.read() != *reference.peek() {
+                    reference.set(staged_reference.read().clone());
+                }
+                if *staged_queries.read() != *queries.peek() {
+                    queries.set(staged_queries.read().clone());
+                }
+                if *staged_excluded.read() != *excluded.peek() {
+                    excluded.set(staged_excluded.read().clone());
+                }
+                if *staged_opts.read() != *run_opts.peek() {
+                    run_opts.set(*staged_opts.read());
+                }
+            },
+        }
+        if pending {
+            { " Input files or options have changed, press Run to apply them.".to_string() }
+        }
+    }
+}
+
 #[component]
 pub fn RunModeSelector(
     kbo_mode: Signal<KboMode>,
@@ -249,3 +754,134 @@ pub fn RunModeSelector(
         }
     }
 }
+
+#[component]
+pub fn SampleSheetSelector(
+    sample_sheet: Signal<Option<SampleSheet>>,
+    reference: Signal<Vec<SeqData>>,
+    queries: Signal<Vec<SeqData>>,
+) -> Element {
+    let mut error: Signal<String> = use_signal(String::new);
+    // Relabel the files that were added before the sheet changed
+    let mut set_sheet = move |sheet: Option<SampleSheet>| {
+        label_samples(&sheet, reference);
+        label_samples(&sheet, queries);
+        sample_sheet.set(sheet);
+    };
+
+    rsx! {
+        div { class: "row",
+              input {
+                  r#type: "file",
+                  accept: ".tsv,.csv,.txt",
+                  multiple: false,
+                  onchange: move |evt| {
+                      error.set(String::new());
+                      async move {
+                          if let Some(file_engine) = &evt.files() {
+                              let files = file_engine.files();
+                              if let Some(file_name) = files.first() {
+                                  if let Some(contents) = file_engine.read_file_to_string(file_name).await {
+                                      match read_sample_sheet(&contents) {
+                                          Ok(sheet) => set_sheet(Some(sheet)),
+                                          Err(e) => error.set("Error: ".to_string() + &e.message),
+                                      }
+                                  }
+                              }
+                          }
+                      }
+                  },
+              }
+              if sample_sheet.read().is_some() {
+                  input {
+                      r#type: "button",
+                      value: "Clear",
+                      onclick: move |_| set_sheet(None),
+                  }
+              }
+        },
+        if let Some(sheet) = &*sample_sheet.read() {
+            table {
+                thead {
+                    tr {
+                        for column in sheet.header.iter() {
+                            th { "{column}" }
+                        }
+                    }
+                }
+                tbody {
+                    for row in sheet.rows.iter() {
+                        tr {
+                            for value in row.iter() {
+                                td { "{value}" }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        div { class: "row",
+              { (*error.read()).clone() },
+        },
+    }
+}
+
+#[component]
+pub fn StagedFileList(
+    out_data: Signal<Vec<SeqData>>,
+) -> Element {
+    if out_data.read().is_empty() {
+        return rsx! { { "".to_string() } }
+    }
+
+    let n_files = out_data.read().len();
+    rsx! {
+        table {
+            thead {
+                tr {
+                    th { "file" }
+                    th { "sample" }
+                    th { "size (MB)" }
+                    th { "contigs" }
+                    th { "" }
+                }
+            }
+            tbody {
+                {
+                    out_data.read().iter().enumerate().map(|(i, seq_data)| {
+                        let size = format!("{:.2}", seq_data.file_size as f64 / 1e6);
+                        let n_contigs = seq_data.contigs.len();
+                        let sample = seq_data.sample.clone().unwrap_or_default();
+                        rsx! {
+                            tr {
+                                td { "{seq_data.file_name}" }
+                                td { "{sample}" }
+                                td { "{size}" }
+                                td { "{n_contigs}" }
+                                td {
+                                    input {
+                                        r#type: "button",
+                                        value: "Up",
+                                        disabled: i == 0,
+                                        onclick: move |_| out_data.write().swap(i - 1, i),
+                                    }
+                                    input {
+                                        r#type: "button",
+                                        value: "Down",
+                                        disabled: i + 1 == n_files,
+                                        onclick: move |_| out_data.write().swap(i, i + 1),
+                                    }
+                                    input {
+                                        r#type: "button",
+                                        value: "Remove",
+                                        onclick: move |_| { out_data.write().remove(i); },
+                                    }
+                                }
+                            }
+                        }
+                    })
+                }
+            }
+        }
+    }
+}
@@ -14,10 +14,14 @@
 use dioxus::prelude::*;
 use crate::dioxus_sortable::*;
 
+use std::borrow::Cow;
+
 use needletail::Sequence;
+use serde::{Deserialize, Serialize};
 
 use crate::common::*;
 use crate::opts::GuiOpts;
+use crate::worker::{Job, JobOutput, Progress};
 
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 enum FindResultField {
@@ -68,7 +72,7 @@ impl Sortable for FindResultField {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct FindResult {
     query_file: String,
     ref_file: String,
@@ -273,21 +277,40 @@ pub fn FindOptsSelector(
                     }
               }
         }
+        div { class: "row-contents",
+              div { class: "column-right",
+                    "Split at N runs longer than",
+              }
+              div { class: "column-left",
+                    input {
+                        r#type: "number",
+                        id: "min_n_run",
+                        name: "min_n_run",
+                        min: "0",
+                        value: opts.read().aln_opts.min_n_run.to_string(),
+                        onchange: move |event| {
+                            let new = event.value().parse::<u64>();
+                            if let Ok(new_len) = new { opts.write().aln_opts.min_n_run = new_len };
+                        }
+                    }
+              }
+        }
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(dead_code)]
 pub struct FindRunnerErr {
     pub code: usize,
     pub message: String,
 }
 
-async fn find_runner(
+pub fn find_runner(
     indexes: &[IndexData],
     queries: &[SeqData],
     ref_file: &str,
     find_opts: kbo::FindOpts,
+    progress: &mut dyn FnMut(Progress),
 ) -> Result<Vec<FindResult>, FindRunnerErr> {
 
     if ref_file.is_empty() {
@@ -300,22 +323,26 @@ async fn find_runner(
         return Err(FindRunnerErr{ code: 1, message: "Argument `indexes` is empty.".to_string() })
     }
 
+    let n_contigs = indexes.len() * queries.iter().map(|query| query.contigs.len()).sum::<usize>();
+    let mut step = 0;
     let res = indexes.iter().flat_map(|index| {
         queries.iter().flat_map(|query| {
             let mut run_lengths: Vec<FindResult> = Vec::new();
 
             // Get local alignments for forward strand
             query.contigs.iter().for_each(|contig| {
+                step += 1;
+                progress(Progress::new("Aligning contig", step, n_contigs));
                 let query_bases = contig.seq.len();
                 let run_lengths_fwd = kbo::find(&contig.seq, &index.sbwt, &index.lcs, find_opts);
                 run_lengths.extend(run_lengths_fwd.iter().map(|x| {
-                    format_find_result(x, query.file_name.clone(), ref_file.to_string(), contig.name.clone(), index.file_name.clone(), query_bases, index.bases, '+')
+                    format_find_result(x, query.label().to_string(), ref_file.to_string(), contig.name.clone(), index.file_name.clone(), query_bases, index.bases, '+')
                 }));
 
                 // Add local alignments for reverse complement
                 let run_lengths_rev = kbo::find(&contig.seq.reverse_complement(), &index.sbwt, &index.lcs, find_opts);
                 run_lengths.extend(run_lengths_rev.iter().map(|x| {
-                    format_find_result(x, query.file_name.clone(), ref_file.to_string(), contig.name.clone(), index.file_name.clone(), query_bases, index.bases, '-')
+                    format_find_result(x, query.label().to_string(), ref_file.to_string(), contig.name.clone(), index.file_name.clone(), query_bases, index.bases, '-')
                 }));
 
             });
@@ -334,10 +361,11 @@ async fn find_runner(
 
 #[component]
 pub fn Find(
-    indexes: ReadOnlySignal<Vec<IndexData>>,
+    indexes: ReadOnlySignal<Vec<IndexInfo>>,
     query_contigs: ReadOnlySignal<Vec<SeqData>>,
     opts: ReadOnlySignal<GuiOpts>,
     result: Signal<Result<Vec<FindResult>, FindRunnerErr>>,
+    progress: Signal<Option<Progress>>,
 ) -> Element {
 
     if indexes.read().is_empty() {
@@ -347,11 +375,40 @@ pub fn Find(
         return rsx! { { "".to_string() } }
     }
 
+    // Results for each index, so adding a file only runs the new index
+    let computed: Signal<IndexResults<Vec<FindResult>>> = use_signal(IndexResults::default);
+
     let _ = use_resource(move || {
-        let ref_name = indexes.read()[0].file_name.clone();
         async move {
-            let res = find_runner(&indexes.read(), &query_contigs.read(), &ref_name, opts.read().to_kbo_find()).await;
-            result.set(res);
+            let indexes = indexes.read().clone();
+            let queries = query_contigs.read().clone();
+            let opts = *opts.read();
+
+            let res = run_per_index(computed, &queries, &indexes, opts, progress, |index, opts| Job::Find {
+                indexes: Cow::Borrowed(std::slice::from_ref(index)),
+                queries: Cow::Borrowed(&queries),
+                ref_file: Cow::Borrowed(&index.file_name),
+                opts,
+            }, |output| match output {
+                // No alignments against this index
+                Ok(JobOutput::Find(Err(e))) if e.code == 0 => Ok(Vec::new()),
+                Ok(JobOutput::Find(res)) => res,
+                Ok(_) => Err(FindRunnerErr{ code: 0, message: "Unexpected result from worker.".to_string() }),
+                Err(e) => Err(FindRunnerErr{ code: 0, message: e.message }),
+            }).await;
+            let res = match res {
+                Some(Ok(res)) => res,
+                Some(Err(e)) => {
+                    result.set(Err(e));
+                    return
+                },
+                None => return,
+            };
+            if res.is_empty() {
+                result.set(Err(FindRunnerErr{ code: 0, message: "No alignments detected.".to_string() }));
+            } else {
+                result.set(Ok(res));
+            }
         }
     }).suspend()?;
 
@@ -377,7 +434,7 @@ pub fn FindRenderer(
         },
         Err(e) => {
             match e.code {
-                0 => rsx! { { "Error: ".to_string() + &e.message } },
+                0 | 2 => rsx! { { "Error: ".to_string() + &e.message } },
                 _ => rsx! { { "" } },
             }
         },
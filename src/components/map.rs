@@ -13,8 +13,13 @@
 //
 use dioxus::prelude::*;
 
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
 use crate::common::*;
 use crate::opts::GuiOpts;
+use crate::worker::{Job, JobOutput, Progress};
 
 #[component]
 pub fn MapOptsSelector(
@@ -78,22 +83,25 @@ pub fn MapOptsSelector(
 }
 
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MapRunnerErr {
     pub code: usize,
     pub message: String,
 }
 
-#[derive(Debug, Default, Clone, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MapResult {
+    #[serde(with = "serde_bytes")]
     aln: Vec<u8>,
     seq_name: String,
+    ref_file: String,
 }
 
-async fn map_runner(
+pub fn map_runner(
     reference: &[SeqData],
     queries: &[IndexData],
     map_opts: kbo::MapOpts,
+    progress: &mut dyn FnMut(Progress),
 ) -> Result<Vec<MapResult>, MapRunnerErr> {
 
     if reference.is_empty() {
@@ -104,13 +112,17 @@ async fn map_runner(
         return Err(MapRunnerErr{ code: 1, message: "Argument `queries` is empty.".to_string() })
     }
 
-    let ref_contigs = reference.first().unwrap();
-    let aln = queries.iter().map(|index| {
-
-        let res: Vec<u8> = ref_contigs.contigs.iter().flat_map(|ref_contig| {
-                                    kbo::map(&ref_contig.seq, &index.sbwt, &index.lcs, map_opts.clone())
-                                }).collect();
-        MapResult { seq_name: index.file_name.clone(), aln: res }
+    let n_contigs = queries.len() * reference.iter().map(|ref_contigs| ref_contigs.contigs.len()).sum::<usize>();
+    let mut step = 0;
+    let aln = reference.iter().flat_map(|ref_contigs| {
+        queries.iter().map(|index| {
+            let res: Vec<u8> = ref_contigs.contigs.iter().flat_map(|ref_contig| {
+                                        step += 1;
+                                        progress(Progress::new("Mapping contig", step, n_contigs));
+                                        kbo::map(&ref_contig.seq, &index.sbwt, &index.lcs, map_opts.clone())
+                                    }).collect();
+            MapResult { seq_name: index.file_name.clone(), aln: res, ref_file: ref_contigs.label().to_string() }
+        }).collect::<Vec<MapResult>>()
     }).collect::<Vec<MapResult>>();
 
     if !aln.is_empty() {
@@ -123,9 +135,10 @@ async fn map_runner(
 #[component]
 pub fn Map(
     ref_contigs: ReadOnlySignal<Vec<SeqData>>,
-    indexes: ReadOnlySignal<Vec<IndexData>>,
+    indexes: ReadOnlySignal<Vec<IndexInfo>>,
     opts: ReadOnlySignal<GuiOpts>,
     result: Signal<Result<Vec<MapResult>, MapRunnerErr>>,
+    progress: Signal<Option<Progress>>,
 ) -> Element {
 
     if ref_contigs.read().is_empty() {
@@ -135,10 +148,27 @@ pub fn Map(
         return rsx! { { "".to_string() } }
     }
 
+    // Results for each index, so adding a file only runs the new index
+    let computed: Signal<IndexResults<Vec<MapResult>>> = use_signal(IndexResults::default);
+
     let _ = use_resource(move || {
         async move {
-            let aln = map_runner(&ref_contigs.read(), &indexes.read(), opts.read().to_kbo_map()).await;
-            result.set(aln);
+            let indexes = indexes.read().clone();
+            let reference = ref_contigs.read().clone();
+            let opts = *opts.read();
+
+            let aln = run_per_index(computed, &reference, &indexes, opts, progress, |index, opts| Job::Map {
+                reference: Cow::Borrowed(&reference),
+                indexes: Cow::Borrowed(std::slice::from_ref(index)),
+                opts,
+            }, |output| match output {
+                Ok(JobOutput::Map(res)) => res,
+                Ok(_) => Err(MapRunnerErr{ code: 0, message: "Unexpected result from worker.".to_string() }),
+                Err(e) => Err(MapRunnerErr{ code: 0, message: e.message }),
+            }).await;
+            if let Some(aln) = aln {
+                result.set(aln);
+            }
         }
     }).suspend()?;
 
@@ -153,8 +183,16 @@ pub fn MapRenderer(
 ) -> Element {
     match &*result.read() {
         Ok(data) => {
+            // One alignment block per reference
+            let mut ref_files: Vec<String> = Vec::new();
+            data.iter().for_each(|x| if !ref_files.contains(&x.ref_file) { ref_files.push(x.ref_file.clone()) });
             rsx! {
-                CopyableMapResult { data: data.to_vec() }
+                for ref_file in ref_files {
+                    div { class: "row",
+                          strong { { ref_file.clone() } },
+                    }
+                    CopyableMapResult { data: data.iter().filter(|x| x.ref_file == ref_file).cloned().collect::<Vec<MapResult>>() }
+                }
             }
         },
         Err(e) => {
@@ -12,7 +12,9 @@
 // at your option.
 //
 
+pub mod cache;
 pub mod common;
+pub mod stats;
 
 // Commands
 pub mod call;
@@ -0,0 +1,161 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+use dioxus::prelude::*;
+
+use crate::common::*;
+use crate::opts::BuildOpts;
+
+#[component]
+pub fn IndexStatistics(
+    index: ReadOnlySignal<Vec<IndexInfo>>,
+) -> Element {
+    if index.read().is_empty() {
+        return rsx! { div { class: "row", "No index has been built." } }
+    }
+
+    rsx! {
+        table {
+            thead {
+                tr {
+                    th { "index" }
+                    th { "k-mers" }
+                    th { "sets" }
+                    th { "SBWT (MB)" }
+                    th { "LCS (MB)" }
+                    th { "time (s)" }
+                    th { "k" }
+                    th { "prefix precalc" }
+                    th { "dedup" }
+                    th { "min base quality" }
+                    th { "min k-mer count" }
+                    th { "exclude soft-masked" }
+                    th { "min contig length" }
+                    th { "memory budget (MB)" }
+                }
+            }
+            tbody {
+                {
+                    index.read().iter().map(|data| {
+                        let stats = &data.stats;
+                        let sbwt_size = format!("{:.2}", stats.sbwt_bytes as f64 / 1e6);
+                        let lcs_size = format!("{:.2}", stats.lcs_bytes as f64 / 1e6);
+                        let build_time = format!("{:.2}", data.build_time_ms as f64 / 1e3);
+                        rsx! {
+                            tr {
+                                td { "{data.file_name}" }
+                                td { "{stats.n_kmers}" }
+                                td { "{stats.n_sets}" }
+                                td { "{sbwt_size}" }
+                                td { "{lcs_size}" }
+                                td { "{build_time}" }
+                                td { "{data.build_opts.kmer_size}" }
+                                td { "{data.build_opts.prefix_precalc}" }
+                                td { "{data.build_opts.dedup_batches}" }
+                                td { "{data.build_opts.min_base_quality}" }
+                                td { "{data.build_opts.min_kmer_count}" }
+                                td { "{data.build_opts.exclude_soft_masked}" }
+                                td { "{data.build_opts.min_contig_len}" }
+                                td { "{data.build_opts.max_memory_mb}" }
+                            }
+                        }
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[component]
+pub fn SequenceQc(
+    seq_data: ReadOnlySignal<Vec<SeqData>>,
+) -> Element {
+    let qc = use_memo(move || {
+        seq_data.read().iter().map(|x| x.qc()).collect::<Vec<SeqQc>>()
+    });
+
+    if seq_data.read().is_empty() {
+        return rsx! { { "".to_string() } }
+    }
+
+    rsx! {
+        table {
+            thead {
+                tr {
+                    th { "file" }
+                    th { "contigs" }
+                    th { "length" }
+                    th { "N50" }
+                    th { "GC%" }
+                    th { "non-ACGT" }
+                    th { "empty" }
+                    th { "duplicate names" }
+                    th { "skipped" }
+                }
+            }
+            tbody {
+                {
+                    seq_data.read().iter().zip(qc.read().iter()).map(|(data, qc)| {
+                        let gc_content = format!("{:.2}", qc.gc_content);
+                        rsx! {
+                            tr {
+                                td { "{data.file_name}" }
+                                td { "{qc.n_contigs}" }
+                                td { "{qc.total_length}" }
+                                td { "{qc.n50}" }
+                                td { "{gc_content}" }
+                                td { "{qc.non_acgt}" }
+                                td { "{qc.empty_records}" }
+                                td { "{qc.duplicate_names}" }
+                                td { "{qc.skipped_records}" }
+                            }
+                        }
+                    })
+                }
+            }
+        }
+        if qc.read().iter().any(|x| x.skipped_records > 0) {
+            div { class: "row",
+                  "Records with names that are not valid UTF-8 were skipped."
+            }
+        }
+    }
+}
+
+#[component]
+pub fn ShortContigs(
+    seq_data: ReadOnlySignal<Vec<SeqData>>,
+    build_opts: ReadOnlySignal<BuildOpts>,
+) -> Element {
+    // Contigs and bases dropped from each file by the length filter
+    let dropped = use_memo(move || {
+        let build_opts = *build_opts.read();
+        seq_data.read().iter().map(|x| {
+            x.contigs.iter().filter(|contig| !crate::util::long_enough(contig, &build_opts))
+                .fold((0, 0), |(n, bases), contig| (n + 1, bases + contig.seq.len()))
+        }).collect::<Vec<(usize, usize)>>()
+    });
+
+    let min_len = build_opts.read().min_contig_len;
+    rsx! {
+        {
+            seq_data.read().iter().zip(dropped.read().iter()).filter(|(_, (n, _))| *n > 0).map(|(data, (n, bases))| {
+                rsx! {
+                    div { class: "row",
+                          "Dropped {n} contigs ({bases} bases) shorter than {min_len} from {data.file_name}."
+                    }
+                }
+            })
+        }
+    }
+}
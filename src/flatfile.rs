@@ -0,0 +1,226 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+// Parser for GenBank and EMBL flat files. Only the sequence and the
+// CDS and gene features are kept.
+//
+use crate::common::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatFileErr {
+    pub code: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    GenBank,
+    Embl,
+}
+
+// Feature keys and qualifiers that are kept
+const FEATURE_KEYS: [&str; 2] = ["CDS", "gene"];
+
+fn detect_format(contents: &str) -> Option<Format> {
+    let first = contents.lines().find(|line| !line.trim().is_empty())?;
+    if first.starts_with("LOCUS") {
+        Some(Format::GenBank)
+    } else if first.starts_with("ID   ") {
+        Some(Format::Embl)
+    } else {
+        None
+    }
+}
+
+/// Returns true if `contents` looks like a GenBank or EMBL file.
+pub fn is_flat_file(contents: &[u8]) -> bool {
+    let head = &contents[..contents.len().min(1024)];
+    // The head may end in the middle of a multibyte character
+    let head = match std::str::from_utf8(head) {
+        Ok(head) => head,
+        Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).unwrap(),
+    };
+    detect_format(head).is_some()
+}
+
+// Parses a location like `complement(join(<1..200,300..>400))` into
+// its outer bounds and strand. Parts on other records, like
+// `AL123456.1:100..200`, are skipped.
+fn parse_location(location: &str) -> Option<(usize, usize, char)> {
+    let strand = if location.contains("complement(") { '-' } else { '+' };
+    let positions: Vec<usize> = location
+        .split([',', '(', ')'])
+        .filter(|part| !part.contains(':'))
+        .flat_map(|part| part.split(|c: char| !c.is_ascii_digit()))
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| x.parse::<usize>().ok())
+        .collect();
+    Some((*positions.iter().min()?, *positions.iter().max()?, strand))
+}
+
+fn parse_feature(lines: &[String]) -> Option<Feature> {
+    let kind = lines[0].split_whitespace().next()?.to_string();
+    if !FEATURE_KEYS.contains(&kind.as_str()) {
+        return None
+    }
+
+    // The location may continue on the lines before the first qualifier
+    let mut location = lines[0].trim_start()[kind.len()..].trim().to_string();
+    let mut qualifiers: Vec<String> = Vec::new();
+    for line in &lines[1..] {
+        let line = line.trim();
+        if line.starts_with('/') {
+            qualifiers.push(line.to_string());
+        } else if let Some(last) = qualifiers.last_mut() {
+            last.push(' ');
+            last.push_str(line);
+        } else {
+            location.push_str(line);
+        }
+    }
+
+    let (start, end, strand) = parse_location(&location)?;
+    let mut feature = Feature { kind, start, end, strand, ..Default::default() };
+    for qualifier in qualifiers {
+        let Some((key, value)) = qualifier[1..].split_once('=') else { continue };
+        let value = value.trim_matches('"').to_string();
+        match key {
+            "gene" => feature.gene = Some(value),
+            "locus_tag" => feature.locus_tag = Some(value),
+            "product" => feature.product = Some(value),
+            _ => (),
+        }
+    }
+    Some(feature)
+}
+
+fn parse_features(lines: &[String]) -> Vec<Feature> {
+    // Features start with the key at column 6, continuation lines are
+    // indented to column 22.
+    let mut features: Vec<Feature> = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    for line in lines {
+        let is_key = line.len() > 5 && line.starts_with("     ") && !line[5..].starts_with(' ');
+        if is_key && !current.is_empty() {
+            features.extend(parse_feature(&current));
+            current.clear();
+        }
+        if is_key || !current.is_empty() {
+            current.push(line.clone());
+        }
+    }
+    if !current.is_empty() {
+        features.extend(parse_feature(&current));
+    }
+    features
+}
+
+fn parse_record(lines: &[&str], format: Format) -> Result<ContigData, FlatFileErr> {
+    let name = match format {
+        Format::GenBank => lines[0].split_whitespace().nth(1),
+        Format::Embl => lines[0].split_whitespace().nth(1).map(|x| x.trim_end_matches(';')),
+    }.ok_or(FlatFileErr{ code: 1, message: "Record is missing a name: ".to_string() + lines[0] })?;
+
+    let mut feature_lines: Vec<String> = Vec::new();
+    let mut seq: Vec<u8> = Vec::new();
+    let mut section = "";
+    for line in &lines[1..] {
+        match format {
+            Format::GenBank => {
+                if !line.starts_with(' ') {
+                    section = line.split_whitespace().next().unwrap_or("");
+                    continue
+                }
+                match section {
+                    "FEATURES" => feature_lines.push(line.to_string()),
+                    "ORIGIN" => seq.extend(line.bytes().filter(|c| c.is_ascii_alphabetic())),
+                    _ => (),
+                }
+            },
+            Format::Embl => {
+                if line.starts_with("FT") {
+                    // Shift EMBL features to the GenBank columns
+                    feature_lines.push("  ".to_string() + line.get(2..).unwrap_or(""));
+                } else if line.starts_with("SQ") {
+                    section = "SQ";
+                } else if section == "SQ" && line.starts_with(' ') {
+                    seq.extend(line.bytes().filter(|c| c.is_ascii_alphabetic()));
+                }
+            },
+        }
+    }
+
+    let seq = needletail::sequence::normalize(&seq, true).unwrap_or(seq);
+    Ok(ContigData { name: name.to_string(), seq, qual: Vec::new(), features: parse_features(&feature_lines), soft_masked: Vec::new() })
+}
+
+/// Parses the records in a GenBank or EMBL file.
+pub fn read_flat_file(contents: &[u8]) -> Result<Vec<ContigData>, FlatFileErr> {
+    let contents = std::str::from_utf8(contents).map_err(|_| FlatFileErr{ code: 1, message: "File is not valid UTF-8.".to_string() })?;
+    let format = detect_format(contents).ok_or(FlatFileErr{ code: 1, message: "Not a GenBank or EMBL file.".to_string() })?;
+
+    let mut records: Vec<ContigData> = Vec::new();
+    let mut lines: Vec<&str> = Vec::new();
+    for line in contents.lines() {
+        if line.starts_with("//") {
+            if !lines.is_empty() {
+                records.push(parse_record(&lines, format)?);
+            }
+            lines.clear();
+        } else if !lines.is_empty() || !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    if !lines.is_empty() {
+        return Err(FlatFileErr{ code: 1, message: "Last record is not terminated by //.".to_string() })
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_genbank_record() {
+        let contents = b"LOCUS       test    20 bp    DNA     linear   BCT 01-JAN-2025
+FEATURES             Location/Qualifiers
+     source          1..20
+                     /organism=\"Escherichia coli\"
+     gene            complement(3..14)
+                     /gene=\"thrL\"
+                     /locus_tag=\"b0001\"
+     CDS             join(3..8,AL123456.1:100..200,
+                     10..14)
+                     /locus_tag=\"b0001\"
+                     /product=\"thr operon leader
+                     peptide\"
+ORIGIN
+        1 acgtacgtac gtacgtacgt
+//
+";
+        let got = read_flat_file(contents).unwrap();
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].name, "test");
+        assert_eq!(got[0].seq, b"ACGTACGTACGTACGTACGT".to_vec());
+        assert_eq!(got[0].features.len(), 2);
+        assert_eq!(got[0].features[0].strand, '-');
+        assert_eq!(got[0].features[0].gene.as_deref(), Some("thrL"));
+        assert_eq!((got[0].features[1].start, got[0].features[1].end), (3, 14));
+        assert_eq!(got[0].features[1].product.as_deref(), Some("thr operon leader peptide"));
+
+        // Features are also read without the usual indentation
+        assert_eq!(parse_feature(&["gene 5..9".to_string()]).map(|x| (x.start, x.end)), Some((5, 9)));
+        assert_eq!(parse_location("J00194.1:100..202"), None);
+    }
+}
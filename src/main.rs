@@ -13,17 +13,31 @@
 //
 #![allow(non_snake_case)]
 
+mod archive;
+mod browser;
+mod cache;
 mod common;
 mod components;
 mod dioxus_sortable;
+mod flatfile;
 mod opts;
+mod reader;
+mod samplesheet;
 mod util;
 mod webgui;
+mod worker;
 
 use dioxus::prelude::launch;
 use dioxus_logger::tracing::{info, Level};
 
 fn main() {
+    // The worker instantiates this same bundle, serve jobs there instead
+    #[cfg(target_arch = "wasm32")]
+    if worker::is_worker() {
+        worker::start_worker();
+        return
+    }
+
     // Init logger
     dioxus_logger::init(Level::INFO).expect("failed to init logger");
     info!("starting app");
@@ -11,8 +11,9 @@
 // the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
 // at your option.
 //
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Default, PartialEq)]
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
 pub struct GuiOpts {
     pub out_opts: OutOpts,
     pub build_opts: BuildOpts,
@@ -44,7 +45,7 @@ impl GuiOpts {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct OutOpts {
     pub interactive: bool,
     pub detailed: bool,
@@ -59,11 +60,19 @@ impl Default for OutOpts {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct BuildOpts {
     pub kmer_size: u32,
     pub dedup_batches: bool,
     pub prefix_precalc: u32,
+    pub max_memory_mb: u64,
+    // Filters for sequencing reads
+    pub min_base_quality: u8,
+    pub min_kmer_count: u32,
+    // Leave out lowercase bases from the index and the queries
+    pub exclude_soft_masked: bool,
+    // Drop contigs shorter than this from the index and the queries
+    pub min_contig_len: u64,
 }
 
 impl Default for BuildOpts {
@@ -72,6 +81,12 @@ impl Default for BuildOpts {
             kmer_size: 51,
             dedup_batches: true,
             prefix_precalc: 8,
+            // wasm32 can address at most 4 GB
+            max_memory_mb: 4096,
+            min_base_quality: 0,
+            min_kmer_count: 1,
+            exclude_soft_masked: false,
+            min_contig_len: 0,
         }
     }
 }
@@ -87,13 +102,15 @@ impl BuildOpts {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct AlnOpts {
     pub max_error_prob: f64,
     pub min_len: u64,
     pub max_gap_len: u64,
     pub do_vc: bool,
     pub do_gapfill: bool,
+    // Split queries at runs of more than this many Ns, 0 to not split
+    pub min_n_run: u64,
 }
 
 impl Default for AlnOpts {
@@ -103,7 +120,8 @@ impl Default for AlnOpts {
             min_len: 100,
             max_gap_len: 0,
             do_vc: true,
-            do_gapfill: true
+            do_gapfill: true,
+            min_n_run: 0,
         }
     }
 }
@@ -0,0 +1,409 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+use std::io::Write;
+use std::sync::Arc;
+
+use needletail::Sequence;
+use needletail::errors::ParseError;
+
+use crate::common::*;
+
+// Enough bytes to recognize the compression format
+const MAGIC_LEN: usize = 6;
+
+fn is_gzip(head: &[u8]) -> bool {
+    head.starts_with(&[0x1F, 0x8B])
+}
+
+fn is_bzip2(head: &[u8]) -> bool {
+    head.starts_with(b"BZh")
+}
+
+fn is_xz(head: &[u8]) -> bool {
+    head.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00])
+}
+
+fn is_zstd(head: &[u8]) -> bool {
+    head.starts_with(&[0x28, 0xB5, 0x2F, 0xFD])
+}
+
+fn decompress_err(format: &str, e: impl ToString) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, "Couldn't decompress ".to_string() + format + " input: " + &e.to_string())
+}
+
+/// Decompresses bzip2, xz or zstd compressed `contents` into `out`.
+fn decompress_into<W: Write>(
+    contents: &[u8],
+    mut out: W,
+) -> std::io::Result<W> {
+    if is_bzip2(contents) {
+        let mut decoder = bzip2_rs::DecoderReader::new(contents);
+        std::io::copy(&mut decoder, &mut out).map_err(|e| decompress_err("bzip2", e))?;
+    } else if is_xz(contents) {
+        lzma_rs::xz_decompress(&mut std::io::BufReader::new(contents), &mut out).map_err(|e| decompress_err("xz", e))?;
+    } else if is_zstd(contents) {
+        // The input may consist of several frames
+        let mut input = contents;
+        while !input.is_empty() {
+            let mut decoder = ruzstd::StreamingDecoder::new(&mut input).map_err(|e| decompress_err("zstd", e))?;
+            std::io::copy(&mut decoder, &mut out).map_err(|e| decompress_err("zstd", e))?;
+        }
+    } else {
+        out.write_all(contents)?;
+    }
+    Ok(out)
+}
+
+// Returns the lowercase runs of `seq` as half-open intervals
+fn lowercase_runs(seq: &[u8]) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    seq.iter().enumerate().for_each(|(i, x)| {
+        match (x.is_ascii_lowercase(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(pos)) => { runs.push((pos, i)); start = None },
+            _ => (),
+        }
+    });
+    if let Some(pos) = start {
+        runs.push((pos, seq.len()));
+    }
+    runs
+}
+
+// Letters that only occur in protein sequences
+const PROTEIN_LETTERS: &[u8] = b"EFIJLOPQZ*";
+
+// Returns true if `seq` has no letters that only occur in proteins, no
+// characters other than letters and gaps, and mostly A, C, G, T, U or N.
+fn is_nucleotide(seq: &[u8]) -> bool {
+    let mut acgtun: usize = 0;
+    let mut ambiguous: usize = 0;
+    for x in seq.iter().map(|x| x.to_ascii_uppercase()) {
+        match x {
+            b'A' | b'C' | b'G' | b'T' | b'U' | b'N' => acgtun += 1,
+            b'-' | b'.' => (),
+            _ if PROTEIN_LETTERS.contains(&x) || !x.is_ascii_alphabetic() => return false,
+            _ => ambiguous += 1,
+        }
+    }
+    // Proteins made of the IUPAC ambiguity letters are mostly ambiguous
+    ambiguous * 4 <= acgtun + ambiguous
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Unknown,
+    Fasta,
+    Fastq,
+    // GenBank, EMBL or anything else needletail decides on
+    Other,
+}
+
+// Parses records from text written to it in pieces. Only the
+// unfinished record at the end of the text is kept in memory.
+#[derive(Default)]
+struct RecordParser {
+    buf: Vec<u8>,
+    format: Option<Format>,
+    // Bytes of `buf` already searched for a record boundary
+    scanned: usize,
+    // FASTQ lines and the end of the last whole record in `buf`
+    lines: usize,
+    boundary: usize,
+    contigs: Vec<ContigData>,
+    skipped: usize,
+    non_nucleotide: Vec<String>,
+    parsed: bool,
+}
+
+impl RecordParser {
+    fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Unknown)
+    }
+
+    // Returns the end of the last whole record in `buf`
+    fn find_boundary(&mut self) -> usize {
+        if self.format() == Format::Unknown && !self.buf.is_empty() {
+            self.format = Some(match self.buf[0] {
+                b'>' => Format::Fasta,
+                b'@' => Format::Fastq,
+                _ => Format::Other,
+            });
+        }
+        let boundary = match self.format() {
+            Format::Fasta => {
+                // A record ends where a line starts with '>'
+                let start = self.scanned.saturating_sub(1);
+                self.buf[start..].windows(2).rposition(|x| x == b"\n>").map(|pos| start + pos + 1).unwrap_or(0)
+            },
+            Format::Fastq => {
+                // Records take up four lines
+                self.buf[self.scanned..].iter().enumerate().for_each(|(i, x)| {
+                    if *x == b'\n' {
+                        self.lines += 1;
+                        if self.lines % 4 == 0 {
+                            self.boundary = self.scanned + i + 1;
+                        }
+                    }
+                });
+                self.boundary
+            },
+            _ => 0,
+        };
+        self.scanned = self.buf.len();
+        boundary
+    }
+
+    fn parse_records(
+        &mut self,
+        end: usize,
+    ) -> Result<(), ParseError> {
+        let mut reader = needletail::parse_fastx_reader(&self.buf[..end])?;
+        while let Some(rec) = reader.next() {
+            let seqrec = rec?;
+            let contig = seqrec.id();
+            if let Ok(contig_name) = std::str::from_utf8(contig) {
+                // Normalizing would turn other letters into nucleotides
+                if !is_nucleotide(&seqrec.seq()) {
+                    self.non_nucleotide.push(contig_name.to_string());
+                }
+                let seq = seqrec.normalize(true);
+                let qual = seqrec.qual().map(|x| x.to_vec()).unwrap_or_default();
+                // Positions only carry over if normalizing removed nothing
+                let raw = seqrec.seq();
+                let soft_masked = if raw.len() == seq.len() { lowercase_runs(&raw) } else { Vec::new() };
+                self.contigs.push(
+                    ContigData {
+                        name: contig_name.to_string(),
+                        seq: seq.to_vec(),
+                        qual,
+                        features: Vec::new(),
+                        soft_masked,
+                    }
+                );
+            } else {
+                self.skipped += 1;
+            }
+        }
+        self.parsed = true;
+        Ok(())
+    }
+
+    fn finish(
+        mut self,
+    ) -> Result<SeqData, ParseError> {
+        if self.format() == Format::Other && crate::flatfile::is_flat_file(&self.buf) {
+            let records = crate::flatfile::read_flat_file(&self.buf)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.message))?;
+            return Ok(SeqData { contigs: records.into_iter().map(Arc::new).collect(), ..Default::default() })
+        }
+        // An empty file is passed on so needletail reports it
+        if !self.buf.is_empty() || !self.parsed {
+            self.parse_records(self.buf.len())?;
+        }
+        Ok(SeqData { contigs: self.contigs.into_iter().map(Arc::new).collect(), skipped_records: self.skipped, non_nucleotide: self.non_nucleotide, ..Default::default() })
+    }
+}
+
+impl Write for RecordParser {
+    fn write(
+        &mut self,
+        data: &[u8],
+    ) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        let end = self.find_boundary();
+        if end > 0 {
+            self.parse_records(end).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.msg))?;
+            self.buf.drain(..end);
+            self.scanned -= end;
+            self.boundary = 0;
+            self.lines %= 4;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Decoder {
+    // Not enough bytes yet to tell the format
+    Pending(Vec<u8>),
+    Plain(RecordParser),
+    Gzip(Box<flate2::write::MultiGzDecoder<RecordParser>>),
+    // The bzip2, xz and zstd decoders pull their input, so the
+    // compressed bytes are kept until the whole file is read
+    Compressed(Vec<u8>),
+}
+
+impl Decoder {
+    fn start(
+        head: &[u8],
+    ) -> std::io::Result<Decoder> {
+        let mut decoder = if is_gzip(head) {
+            Decoder::Gzip(Box::new(flate2::write::MultiGzDecoder::new(RecordParser::default())))
+        } else if is_bzip2(head) || is_xz(head) || is_zstd(head) {
+            Decoder::Compressed(Vec::new())
+        } else {
+            Decoder::Plain(RecordParser::default())
+        };
+        decoder.write_all(head)?;
+        Ok(decoder)
+    }
+}
+
+impl Write for Decoder {
+    fn write(
+        &mut self,
+        data: &[u8],
+    ) -> std::io::Result<usize> {
+        match self {
+            Decoder::Pending(head) => {
+                head.extend_from_slice(data);
+                if head.len() >= MAGIC_LEN {
+                    let head = std::mem::take(head);
+                    *self = Decoder::start(&head)?;
+                }
+                Ok(data.len())
+            },
+            Decoder::Plain(parser) => parser.write(data),
+            Decoder::Gzip(decoder) => decoder.write(data).map_err(|e| decompress_err("gzip", e)),
+            Decoder::Compressed(contents) => contents.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Parses a sequence file that is read in chunks.
+///
+/// Records are parsed as soon as they are complete, so the raw file
+/// contents and the parsed contigs aren't kept in memory at the same
+/// time. Plain and gzipped inputs are decompressed on the fly.
+pub struct SeqReader {
+    decoder: Decoder,
+    file_size: usize,
+}
+
+impl Default for SeqReader {
+    fn default() -> Self {
+        SeqReader { decoder: Decoder::Pending(Vec::new()), file_size: 0 }
+    }
+}
+
+impl SeqReader {
+    /// Parses the records that `chunk` completes.
+    pub fn push(
+        &mut self,
+        chunk: &[u8],
+    ) -> Result<(), ParseError> {
+        self.file_size += chunk.len();
+        self.decoder.write_all(chunk)?;
+        Ok(())
+    }
+
+    /// Parses the remaining records into the contents of `file_name`.
+    pub fn finish(
+        self,
+        file_name: &str,
+    ) -> Result<SeqData, ParseError> {
+        let decoder = match self.decoder {
+            Decoder::Pending(head) => Decoder::start(&head)?,
+            decoder => decoder,
+        };
+        let parser = match decoder {
+            Decoder::Pending(_) => unreachable!(),
+            Decoder::Plain(parser) => parser,
+            Decoder::Gzip(decoder) => decoder.finish().map_err(|e| decompress_err("gzip", e))?,
+            Decoder::Compressed(contents) => decompress_into(&contents, RecordParser::default())?,
+        };
+        Ok(SeqData { file_name: file_name.to_string(), file_size: self.file_size, ..parser.finish()? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn read_records_in_chunks() {
+        use super::SeqReader;
+        use std::io::Write;
+
+        let fasta = b">first contig\nACGTAC\nGTACGT\n>second\nacgtnN\n>third\nTTTT\n".to_vec();
+        let fastq = b"@read1\nACGT\n+\nIIII\n@read2\nGGCC\n+\n@@@@\n".to_vec();
+        let protein = b">nucleotides\nACGTRYACGT\n>protein\nMKVLAAGIVG\n".to_vec();
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&fasta).unwrap();
+        let gzip = gzip.finish().unwrap();
+
+        for contents in [fasta, fastq, gzip, protein.clone()] {
+            let mut whole = SeqReader::default();
+            whole.push(&contents).unwrap();
+            let expected = whole.finish("whole").unwrap();
+            assert!(!expected.contigs.is_empty());
+
+            for chunk_size in [1, 3, 7] {
+                let mut reader = SeqReader::default();
+                contents.chunks(chunk_size).for_each(|chunk| reader.push(chunk).unwrap());
+                let got = reader.finish("whole").unwrap();
+                assert!(got == expected);
+            }
+        }
+
+        let mut reader = SeqReader::default();
+        reader.push(&protein).unwrap();
+        assert_eq!(reader.finish("protein").unwrap().non_nucleotide, vec!["protein".to_string()]);
+    }
+
+    #[test]
+    fn decompress_bzip2_xz_and_zstd() {
+        use super::{decompress_into, SeqReader};
+
+        // Compressed ">c\nACGTACGT\n"
+        let bzip2: &[u8] = &[
+            0x42, 0x5A, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0x87, 0xF3, 0x8D, 0x8A, 0x00, 0x00,
+            0x01, 0x4F, 0x00, 0x00, 0x10, 0x00, 0x01, 0x28, 0x80, 0x04, 0x00, 0x08, 0x00, 0x20, 0x00, 0x21,
+            0xA3, 0x4C, 0xD4, 0x21, 0x80, 0xA3, 0x7B, 0x15, 0x42, 0x3C, 0x5D, 0xC9, 0x14, 0xE1, 0x42, 0x42,
+            0x1F, 0xCE, 0x36, 0x28,
+        ];
+        let xz: &[u8] = &[
+            0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, 0x00, 0x04, 0xE6, 0xD6, 0xB4, 0x46, 0x04, 0xC0, 0x10, 0x0C,
+            0x21, 0x01, 0x16, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7B, 0xB0, 0x54, 0x28,
+            0x01, 0x00, 0x0B, 0x3E, 0x63, 0x0A, 0x41, 0x43, 0x47, 0x54, 0x41, 0x43, 0x47, 0x54, 0x0A, 0x00,
+            0x6A, 0xE4, 0xC0, 0x8C, 0x36, 0xD3, 0x02, 0xE1, 0x00, 0x01, 0x2C, 0x0C, 0xAE, 0x92, 0x01, 0x10,
+            0x1F, 0xB6, 0xF3, 0x7D, 0x01, 0x00, 0x00, 0x00, 0x00, 0x04, 0x59, 0x5A,
+        ];
+        // The zstd file has a second frame with ">d\nTTGG\n"
+        let zstd: &[u8] = &[
+            0x28, 0xB5, 0x2F, 0xFD, 0x24, 0x0C, 0x61, 0x00, 0x00, 0x3E, 0x63, 0x0A, 0x41, 0x43, 0x47, 0x54,
+            0x41, 0x43, 0x47, 0x54, 0x0A, 0x3D, 0x9B, 0x36, 0x7D, 0x28, 0xB5, 0x2F, 0xFD, 0x24, 0x08, 0x41,
+            0x00, 0x00, 0x3E, 0x64, 0x0A, 0x54, 0x54, 0x47, 0x47, 0x0A, 0xB3, 0xA2, 0xED, 0x73,
+        ];
+
+        let expected = b">c\nACGTACGT\n".to_vec();
+        assert_eq!(decompress_into(bzip2, Vec::new()).unwrap(), expected);
+        assert_eq!(decompress_into(xz, Vec::new()).unwrap(), expected);
+        assert_eq!(decompress_into(zstd, Vec::new()).unwrap(), b">c\nACGTACGT\n>d\nTTGG\n".to_vec());
+        assert!(decompress_into(&bzip2[..20], Vec::new()).is_err());
+
+        let mut reader = SeqReader::default();
+        zstd.chunks(5).for_each(|chunk| reader.push(chunk).unwrap());
+        let got = reader.finish("ab.fa.zst").unwrap();
+        assert_eq!(got.contigs.len(), 2);
+        assert_eq!(got.contigs[1].seq, b"TTGG");
+    }
+}
@@ -0,0 +1,112 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+// Sample sheets that map input file names to sample IDs. The sheet is
+// a TSV or CSV file with a header line naming a sample and a file
+// column; any other columns are kept as metadata.
+//
+use crate::common::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleSheetErr {
+    pub code: usize,
+    pub message: String,
+}
+
+// Accepted names for the required columns
+const SAMPLE_COLUMNS: [&str; 3] = ["sample", "sample_id", "id"];
+const FILE_COLUMNS: [&str; 5] = ["file", "file_name", "filename", "fasta", "path"];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleSheet {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    sample_col: usize,
+    file_col: usize,
+}
+
+// True if `path` is `file_name` or ends in it after a directory, so that
+// sheets listing paths match uploaded files and files read from archives
+fn is_path_to(path: &str, file_name: &str) -> bool {
+    let path = path.replace('\\', "/");
+    path == file_name || path.ends_with(&("/".to_string() + file_name))
+}
+
+impl SampleSheet {
+    /// Returns the sample ID listed for `file_name`.
+    pub fn sample(&self, file_name: &str) -> Option<&str> {
+        self.rows.iter()
+            .find(|row| is_path_to(&row[self.file_col], file_name))
+            .map(|row| row[self.sample_col].as_str())
+    }
+
+    /// Returns the sample ID of each file in `seq_data`.
+    pub fn labels(&self, seq_data: &[SeqData]) -> Vec<Option<String>> {
+        seq_data.iter().map(|x| self.sample(&x.file_name).map(|sample| sample.to_string())).collect()
+    }
+}
+
+/// Reads a tab or comma separated sample sheet.
+pub fn read_sample_sheet(
+    contents: &str,
+) -> Result<SampleSheet, SampleSheetErr> {
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#'));
+    let header_line = lines.next().ok_or(SampleSheetErr{ code: 0, message: "Sample sheet is empty.".to_string() })?;
+    let separator = if header_line.contains('\t') { '\t' } else { ',' };
+    let split = |line: &str| line.split(separator).map(|x| x.trim().trim_matches('"').to_string()).collect::<Vec<String>>();
+
+    let header = split(header_line);
+    let find_column = |names: &[&str]| header.iter().position(|x| names.contains(&x.to_lowercase().as_str()));
+    let sample_col = find_column(&SAMPLE_COLUMNS)
+        .ok_or(SampleSheetErr{ code: 1, message: "Sample sheet has no sample column (".to_string() + &SAMPLE_COLUMNS.join(", ") + ")." })?;
+    let file_col = find_column(&FILE_COLUMNS)
+        .ok_or(SampleSheetErr{ code: 1, message: "Sample sheet has no file column (".to_string() + &FILE_COLUMNS.join(", ") + ")." })?;
+
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    for (i, line) in lines.enumerate() {
+        let mut row = split(line);
+        if row.len() > header.len() {
+            return Err(SampleSheetErr{ code: 2, message: "Line ".to_string() + &(i + 2).to_string() + " of the sample sheet has more columns than the header." })
+        }
+        // Metadata columns are optional
+        row.resize(header.len(), String::new());
+        if row[sample_col].is_empty() || row[file_col].is_empty() {
+            return Err(SampleSheetErr{ code: 2, message: "Line ".to_string() + &(i + 2).to_string() + " of the sample sheet is missing the sample or the file." })
+        }
+        rows.push(row);
+    }
+
+    Ok(SampleSheet { header, rows, sample_col, file_col })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn read_sample_sheet_matches_files() {
+        use super::read_sample_sheet;
+
+        let tsv = "sample\tfile\tsource\nS1\tdata/a.fasta.gz\tblood\nS2\tb.fa\n";
+        let sheet = read_sample_sheet(tsv).unwrap();
+        assert_eq!(sheet.sample("a.fasta.gz"), Some("S1"));
+        assert_eq!(sheet.sample("b.fa"), Some("S2"));
+        assert_eq!(sheet.sample("c.fa"), None);
+        assert_eq!(sheet.sample("data/a.fasta.gz"), Some("S1"));
+        assert_eq!(sheet.sample("ta/a.fasta.gz"), None);
+        assert_eq!(sheet.rows[1], vec!["S2".to_string(), "b.fa".to_string(), String::new()]);
+
+        let csv = "File_Name,Sample_ID\n\"a.fasta.gz\",S1\n";
+        assert_eq!(read_sample_sheet(csv).unwrap().sample("a.fasta.gz"), Some("S1"));
+
+        assert_eq!(read_sample_sheet("name,path\nS1,a.fa\n").unwrap_err().code, 1);
+    }
+}
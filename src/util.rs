@@ -11,18 +11,91 @@
 // the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
 // at your option.
 //
-use std::ops::Deref;
+use std::borrow::Cow;
+use std::sync::Arc;
 
-use needletail::Sequence;
 use needletail::errors::ParseError;
+use serde::{Deserialize, Serialize};
 
 use crate::common::*;
+use crate::opts::BuildOpts;
+use crate::worker::Progress;
 
-#[allow(dead_code)]
-#[derive(Debug,Clone)]
+// Error codes:
+//   0: no index was built
+//   1: nothing to index
+//   2: the worker failed
+//   3: cancelled
+//   4: over the memory budget
+//   5: invalid build options
+//   6: input file contains no sequences
+//   7: input sequence is shorter than k
+//   8: no contig passes the length filter
+//   9: no k-mer passes the k-mer count filter
+#[derive(Debug,Clone,Serialize,Deserialize)]
 pub struct BuilderErr {
-    code: usize,
-    message: String,
+    pub code: usize,
+    pub message: String,
+}
+
+impl BuilderErr {
+    fn invalid_kmer_size(build_opts: &BuildOpts) -> BuilderErr {
+        BuilderErr{ code: 5, message: "k-mer size ".to_string() + &build_opts.kmer_size.to_string() + " is not between 2 and 255." }
+    }
+
+    fn empty_file(file_name: &str) -> BuilderErr {
+        BuilderErr{ code: 6, message: "File ".to_string() + file_name + " contains no sequences." }
+    }
+
+    fn too_short(file_name: &str, contig: Option<&str>, build_opts: &BuildOpts) -> BuilderErr {
+        let what = match contig {
+            Some(contig_name) => "Contig ".to_string() + contig_name + " in file " + file_name + " is",
+            None => "All sequences in file ".to_string() + file_name + " are",
+        };
+        BuilderErr{ code: 7, message: what + " shorter than the k-mer size " + &build_opts.kmer_size.to_string() + "." }
+    }
+
+    fn all_filtered(file_name: &str, build_opts: &BuildOpts) -> BuilderErr {
+        BuilderErr{ code: 8, message: "All contigs in file ".to_string() + file_name + " are shorter than the minimum contig length " + &build_opts.min_contig_len.to_string() + "." }
+    }
+
+    fn no_solid_kmers(file_name: &str, build_opts: &BuildOpts) -> BuilderErr {
+        BuilderErr{ code: 9, message: "No k-mers in ".to_string() + file_name + " occur at least " + &build_opts.min_kmer_count.to_string() + " times." }
+    }
+}
+
+/// Returns true if `contig` passes the minimum contig length filter.
+pub fn long_enough(contig: &ContigData, build_opts: &BuildOpts) -> bool {
+    contig.seq.len() as u64 >= build_opts.min_contig_len
+}
+
+// Check the input before indexing so that the error can name the
+// file and contig instead of failing inside the SBWT construction.
+fn validate_build_input(
+    seq_data: &SeqData,
+    separately: bool,
+    build_opts: &BuildOpts,
+) -> Result<(), BuilderErr> {
+    if !(2..=255).contains(&build_opts.kmer_size) {
+        return Err(BuilderErr::invalid_kmer_size(build_opts))
+    }
+    if seq_data.contigs.iter().all(|contig| contig.seq.is_empty()) {
+        return Err(BuilderErr::empty_file(&seq_data.file_name))
+    }
+    if !seq_data.contigs.iter().any(|contig| long_enough(contig, build_opts)) {
+        return Err(BuilderErr::all_filtered(&seq_data.file_name, build_opts))
+    }
+
+    let k = build_opts.kmer_size as usize;
+    let mut kept = seq_data.contigs.iter().filter(|contig| long_enough(contig, build_opts));
+    if separately {
+        if let Some(contig) = kept.find(|contig| contig.seq.len() < k) {
+            return Err(BuilderErr::too_short(&seq_data.file_name, Some(&contig.name), build_opts))
+        }
+    } else if kept.all(|contig| contig.seq.len() < k) {
+        return Err(BuilderErr::too_short(&seq_data.file_name, None, build_opts))
+    }
+    Ok(())
 }
 
 pub fn build_sbwt(
@@ -33,106 +106,674 @@ pub fn build_sbwt(
     kbo::index::build_sbwt_from_vecs(ref_data, &build_opts)
 }
 
+// Rough upper bound for the memory used by an index over `bases`
+// nucleotides, and by the k-mer sorting needed to construct it.
+fn estimate_index_memory(bases: usize, build_opts: &BuildOpts) -> (usize, usize) {
+    let k = build_opts.kmer_size as usize;
+    // Forward and reverse complement k-mers
+    let n_kmers = 2 * bases;
+
+    // Four bit vectors with rank support and the LCS array
+    let lcs_bits = (usize::BITS - k.leading_zeros()) as usize;
+    let index = n_kmers * 5 / 8 + n_kmers * lcs_bits / 8;
+
+    // (k+1)-mers are sorted bit-packed in 64-bit words, without
+    // deduplication the batches are kept whole until merged.
+    let kmer_bytes = (2 * (k + 1)).div_ceil(64) * 8;
+    let copies = if build_opts.dedup_batches { 1 } else { 2 };
+    let mut construction = n_kmers * kmer_bytes * copies;
+    if build_opts.min_kmer_count > 1 {
+        construction += SKETCH_ROWS << SKETCH_BITS;
+    }
+
+    (index, construction)
+}
+
+// Peak memory estimate for building indexes of the given sizes one
+// after another: the finished indexes plus the largest construction.
+pub fn estimate_build_memory(sizes: &[usize], build_opts: &BuildOpts) -> usize {
+    let estimates: Vec<(usize, usize)> = sizes.iter().map(|bases| estimate_index_memory(*bases, build_opts)).collect();
+    let indexes: usize = estimates.iter().map(|x| x.0).sum();
+    let construction: usize = estimates.iter().map(|x| x.1).max().unwrap_or(0);
+    indexes + construction
+}
+
+fn check_memory_budget(sizes: &[usize], build_opts: &BuildOpts) -> Result<(), BuilderErr> {
+    let estimate_mb = estimate_build_memory(sizes, build_opts).div_ceil(1024 * 1024);
+    if estimate_mb as u64 > build_opts.max_memory_mb {
+        return Err(BuilderErr{ code: 4, message: "Indexing needs an estimated ".to_string() + &estimate_mb.to_string() + " MB of memory, which is over the budget of " + &build_opts.max_memory_mb.to_string() + " MB. Use fewer or smaller inputs, a smaller k-mer size, or increase the memory budget in the indexing options." })
+    }
+    Ok(())
+}
+
 pub async fn build_indexes(
     queries: &[SeqData],
-    build_opts: kbo::BuildOpts,
-) -> Vec<IndexData> {
-    let query_data: Vec<(String, Vec<Vec<u8>>)> = queries.iter()
-                                                                .map(|query| { (
-                                                                    query.file_name.clone(),
-                                                                    query.contigs.iter().map(|contig| {
-                                                                        contig.seq.clone()
-                                                                    }).collect::<Vec<Vec<u8>>>()
-                                                                )
-                                                                }).collect();
-    let mut indexes: Vec<IndexData> = Vec::with_capacity(query_data.len());
-    for (file_name, seq_data) in query_data {
-        let (sbwt, lcs) = crate::util::sbwt_builder(&seq_data, build_opts.clone()).await.unwrap();
-        let index = IndexData { sbwt, lcs, file_name: file_name.clone(), bases: seq_data.iter().map(|x| x.len()).sum() };
-        indexes.push(index);
+    build_opts: BuildOpts,
+    mut progress: impl FnMut(Progress),
+) -> Result<Vec<IndexInfo>, BuilderErr> {
+    for query in queries {
+        validate_build_input(query, false, &build_opts)?;
+    }
+    // Indexes of removed files are no longer needed
+    let keys: Vec<String> = queries.iter().map(|query| crate::cache::cache_key(query, &build_opts, false)).collect();
+    let cached = lookup_indexes(&keys).await?;
+
+    // Only the files that aren't cached count towards the budget
+    let sizes: Vec<usize> = queries.iter().zip(cached.iter()).filter(|(_, hit)| hit.is_none()).map(|(query, _)| {
+        query.contigs.iter().filter(|contig| long_enough(contig, &build_opts)).map(|contig| contig.seq.len()).sum()
+    }).collect();
+    check_memory_budget(&sizes, &build_opts)?;
+
+    let mut indexes: Vec<IndexInfo> = Vec::with_capacity(queries.len());
+    for (i, (query, hit)) in queries.iter().zip(cached).enumerate() {
+        progress(Progress::new("Indexing file", i + 1, queries.len()));
+        if let Some(mut hit) = hit {
+            // Same contents may have been cached under another file name
+            hit[0].file_name = query.label().to_string();
+            indexes.append(&mut hit);
+            continue
+        }
+
+        let mut input = IndexInput { name: query.label().to_string(), ..Default::default() };
+        query.contigs.iter().filter(|contig| long_enough(contig, &build_opts)).for_each(|contig| input.push(contig, &build_opts));
+        let mut index = crate::util::sbwt_builder(&keys[i], vec![input], build_opts, |_| {}).await?;
+        indexes.append(&mut index);
     };
-    indexes
+    Ok(indexes)
 }
 
 pub async fn build_runner(
     reference: &[SeqData],
-    build_opts: kbo::BuildOpts,
+    build_opts: BuildOpts,
     separately: bool,
-) -> Result<Vec<IndexData>, BuilderErr> {
+    mut progress: impl FnMut(Progress) + 'static,
+) -> Result<Vec<IndexInfo>, BuilderErr> {
 
     if reference.is_empty() {
         return Err(BuilderErr{ code: 1, message: "Argument `reference` is empty.".to_string() })
     }
 
     let ref_contigs = reference.first().unwrap();
+    validate_build_input(ref_contigs, separately, &build_opts)?;
 
-    let res = if !separately {
-        let seq_data: Vec<u8> = ref_contigs.contigs.iter().flat_map(|contig| contig.seq.clone()).collect::<Vec<u8>>();
-        let bases: usize = seq_data.len();
-        let data = &[seq_data];
-        let index = crate::util::sbwt_builder(
-            data,
-            build_opts.clone(),
-        );
-        let index = index.await.unwrap();
-        vec![IndexData { sbwt: index.0, lcs: index.1, file_name: ref_contigs.file_name.clone(), bases }]
+    let cache_key = crate::cache::cache_key(ref_contigs, &build_opts, separately);
+    if let Some(mut cached) = lookup_indexes(std::slice::from_ref(&cache_key)).await?.remove(0) {
+        if !separately {
+            cached[0].file_name = ref_contigs.label().to_string();
+        }
+        return Ok(cached)
+    }
+
+    let kept = || ref_contigs.contigs.iter().filter(|contig| long_enough(contig, &build_opts));
+    let sizes: Vec<usize> = if separately {
+        kept().map(|contig| contig.seq.len()).collect()
     } else {
-        let seq_data: Vec<(String, Vec<u8>)> = ref_contigs.contigs.iter().map(|contig| (contig.name.clone(), contig.seq.clone())).collect::<Vec<(String, Vec<u8>)>>();
-
-        let mut indexes: Vec<IndexData> = Vec::new();
-        for (contig_name, contig_seq) in seq_data {
-            let bases = contig_seq.len();
-            let data = &[contig_seq];
-            let index = crate::util::sbwt_builder(
-                data,
-                build_opts.clone(),
-            );
-            let index = index.await.unwrap();
-            indexes.push(IndexData { sbwt: index.0, lcs: index.1, file_name: contig_name, bases });
-        }
-        indexes
+        vec![kept().map(|contig| contig.seq.len()).sum()]
     };
+    check_memory_budget(&sizes, &build_opts)?;
 
+    let inputs: Vec<IndexInput> = if !separately {
+        progress(Progress::new("Indexing file", 1, 1));
+        let seq_data: Vec<u8> = kept().filter(|contig| !has_qualities(contig)).flat_map(|contig| mask_bases(contig, &build_opts)).collect::<Vec<u8>>();
+        let mut input = IndexInput { name: ref_contigs.label().to_string(), ..Default::default() };
+        if !seq_data.is_empty() {
+            input.seqs.push(seq_data);
+        }
+        kept().filter(|contig| has_qualities(contig)).for_each(|contig| input.push(contig, &build_opts));
+        vec![input]
+    } else {
+        kept().map(|contig| {
+            let mut input = IndexInput { name: contig.name.clone(), ..Default::default() };
+            input.push(contig, &build_opts);
+            input
+        }).collect()
+    };
+
+    let res = crate::util::sbwt_builder(&cache_key, inputs, build_opts, progress).await?;
     if !res.is_empty() {
         return Ok(res)
     }
     Err(BuilderErr{ code: 0, message: "Couldn't index reference data.".to_string() })
 }
 
-pub async fn read_seq_data(file_contents: &Vec<u8>) -> Result<Vec<ContigData>, ParseError> {
-    let mut seq_data: Vec<ContigData> = Vec::new();
-    let mut reader = needletail::parse_fastx_reader(file_contents.deref())?;
-    while let Some(rec) = reader.next() {
-        let seqrec = rec?;
-        let contig = seqrec.id();
-        if let Ok(contig_name) = std::str::from_utf8(contig) {
-            let seq = seqrec.normalize(true);
-            seq_data.push(
-                ContigData {
-                    name: contig_name.to_string(),
-                    seq: seq.to_vec(),
+/// Sequences to build one index from. Only the `reads`, records that
+/// carry base qualities, go through the k-mer count filter.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct IndexInput {
+    pub name: String,
+    pub seqs: Vec<Vec<u8>>,
+    pub reads: Vec<Vec<u8>>,
+}
+
+impl IndexInput {
+    fn push(&mut self, contig: &ContigData, build_opts: &BuildOpts) {
+        let seq = mask_bases(contig, build_opts);
+        if has_qualities(contig) {
+            self.reads.push(seq);
+        } else {
+            self.seqs.push(seq);
+        }
+    }
+}
+
+fn has_qualities(contig: &ContigData) -> bool {
+    !contig.qual.is_empty() && contig.qual.len() == contig.seq.len()
+}
+
+// Replaces bases below `min_base_quality` and, if they are excluded,
+// soft-masked bases with N so that no k-mer containing them is indexed.
+fn mask_bases(contig: &ContigData, build_opts: &BuildOpts) -> Vec<u8> {
+    let mut seq = if build_opts.min_base_quality == 0 || !has_qualities(contig) {
+        contig.seq.clone()
+    } else {
+        contig.seq.iter().zip(contig.qual.iter()).map(|(base, qual)| {
+            if qual.saturating_sub(33) < build_opts.min_base_quality { b'N' } else { *base }
+        }).collect()
+    };
+    if build_opts.exclude_soft_masked {
+        contig.soft_masked.iter().for_each(|(start, end)| seq[*start..*end].fill(b'N'));
+    }
+    seq
+}
+
+// Returns the half-open intervals of `seq` between runs of more than
+// `min_n_run` Ns.
+fn split_at_n_runs(seq: &[u8], min_n_run: usize) -> Vec<(usize, usize)> {
+    let mut pieces: Vec<(usize, usize)> = Vec::new();
+    let mut start: usize = 0;
+    let mut run: usize = 0;
+    seq.iter().enumerate().for_each(|(i, base)| {
+        if *base == b'N' {
+            run += 1;
+            return
+        }
+        if run > min_n_run && i - run > start {
+            pieces.push((start, i - run));
+        }
+        if run > min_n_run {
+            start = i;
+        }
+        run = 0;
+    });
+    let end = if run > min_n_run { seq.len() - run } else { seq.len() };
+    if end > start {
+        pieces.push((start, end));
+    }
+    pieces
+}
+
+/// Applies the contig length filter and soft-masking in `build_opts`
+/// to the query or reference contigs in `seq_data`, and splits them at
+/// runs of more than `min_n_run` Ns unless it is 0. Pieces of split
+/// contigs are named by their 1-based inclusive coordinates.
+pub fn prepare_queries<'a>(
+    seq_data: &'a [SeqData],
+    build_opts: &BuildOpts,
+    min_n_run: usize,
+) -> Cow<'a, [SeqData]> {
+    let masked = build_opts.exclude_soft_masked && seq_data.iter().any(|x| x.contigs.iter().any(|contig| !contig.soft_masked.is_empty()));
+    let filtered = seq_data.iter().any(|x| x.contigs.iter().any(|contig| !long_enough(contig, build_opts)));
+    if !masked && !filtered && min_n_run == 0 {
+        return Cow::Borrowed(seq_data)
+    }
+    Cow::Owned(seq_data.iter().map(|file| {
+        let contigs = file.contigs.iter().filter(|contig| long_enough(contig, build_opts)).flat_map(|contig| {
+            let mask = build_opts.exclude_soft_masked && !contig.soft_masked.is_empty();
+            let mut seq = Cow::Borrowed(&contig.seq);
+            if mask {
+                contig.soft_masked.iter().for_each(|(start, end)| seq.to_mut()[*start..*end].fill(b'N'));
+            }
+            let pieces = if min_n_run > 0 { split_at_n_runs(&seq, min_n_run) } else { vec![(0, seq.len())] };
+            if pieces.len() == 1 && pieces[0] == (0, seq.len()) {
+                if !mask {
+                    return vec![Arc::clone(contig)]
                 }
-            );
+                return vec![Arc::new(ContigData { name: contig.name.clone(), seq: seq.into_owned(), qual: contig.qual.clone(), features: contig.features.clone(), soft_masked: Vec::new() })]
+            }
+            let id = contig.name.split_whitespace().next().unwrap_or_default();
+            pieces.iter().map(|(start, end)| {
+                Arc::new(ContigData {
+                    name: id.to_string() + ":" + &(start + 1).to_string() + "-" + &end.to_string(),
+                    seq: seq[*start..*end].to_vec(),
+                    qual: if contig.qual.len() == seq.len() { contig.qual[*start..*end].to_vec() } else { Vec::new() },
+                    features: Vec::new(),
+                    soft_masked: Vec::new(),
+                })
+            }).collect::<Vec<Arc<ContigData>>>()
+        }).collect::<Vec<Arc<ContigData>>>();
+        SeqData { contigs, file_name: file.file_name.clone(), file_size: file.file_size, skipped_records: file.skipped_records, sample: file.sample.clone(), non_nucleotide: file.non_nucleotide.clone() }
+    }).collect())
+}
+
+// Rolling hashes of the forward and reverse complement k-mers (ntHash).
+fn base_hash(base: u8) -> Option<u64> {
+    match base {
+        b'A' => Some(0x3c8bfbb395c60474),
+        b'C' => Some(0x3193c18562a02b4c),
+        b'G' => Some(0x20323ed082572324),
+        b'T' => Some(0x295549f54be24456),
+        _ => None,
+    }
+}
+
+fn complement(base: u8) -> u8 {
+    match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        _ => base,
+    }
+}
+
+// Calls `f` with the start position and canonical hash of every k-mer
+// in `seq` that contains only ACGT.
+fn for_each_kmer_hash(seq: &[u8], k: usize, mut f: impl FnMut(usize, u64)) {
+    let mut fwd: u64 = 0;
+    let mut rev: u64 = 0;
+    let mut valid: usize = 0;
+    for (i, base) in seq.iter().enumerate() {
+        let Some(h_in) = base_hash(*base) else {
+            valid = 0;
+            continue
+        };
+        let rc_in = base_hash(complement(*base)).unwrap();
+        if valid == 0 {
+            fwd = 0;
+            rev = 0;
         }
+        if valid < k {
+            // Build up the first k-mer after a non-ACGT base
+            fwd = fwd.rotate_left(1) ^ h_in;
+            rev ^= rc_in.rotate_left(valid as u32);
+            valid += 1;
+        } else {
+            let out = seq[i - k];
+            let h_out = base_hash(out).unwrap();
+            let rc_out = base_hash(complement(out)).unwrap();
+            fwd = fwd.rotate_left(1) ^ h_out.rotate_left(k as u32) ^ h_in;
+            rev = rev.rotate_right(1) ^ rc_out.rotate_right(1) ^ rc_in.rotate_left((k - 1) as u32);
+        }
+        if valid == k {
+            f(i + 1 - k, fwd.min(rev));
+        }
+    }
+}
+
+// Count-min sketch with saturating 8-bit counters, which bounds the
+// memory used for counting k-mers in a large read set.
+const SKETCH_ROWS: usize = 4;
+const SKETCH_BITS: u32 = 24;
+
+fn sketch_slots(hash: u64) -> [usize; SKETCH_ROWS] {
+    const SEEDS: [u64; SKETCH_ROWS] = [0x9e3779b97f4a7c15, 0xc2b2ae3d27d4eb4f, 0x165667b19e3779f9, 0xd6e8feb86659fd93];
+    let mut slots = [0; SKETCH_ROWS];
+    for (row, seed) in SEEDS.iter().enumerate() {
+        slots[row] = (row << SKETCH_BITS) | (hash.wrapping_mul(*seed) >> (64 - SKETCH_BITS)) as usize;
     }
-    Ok(seq_data)
+    slots
 }
 
-pub async fn read_fasta_files(
-    files: &Vec<(String, Vec<u8>)>,
-) -> Result<Vec<SeqData>, ParseError> {
-    let mut contigs: Vec<SeqData> = Vec::with_capacity(files.len());
-    for (filename, contents) in files {
-        let data = crate::util::read_seq_data(contents).await?;
-        contigs.push(SeqData { contigs: data, file_name: filename.clone() });
+/// Splits `seq_data` into the stretches covered by k-mers that occur
+/// at least `min_count` times, counting both strands together.
+pub fn filter_by_kmer_count(
+    seq_data: &[Vec<u8>],
+    k: usize,
+    min_count: u32,
+) -> Vec<Vec<u8>> {
+    let mut sketch: Vec<u8> = vec![0; SKETCH_ROWS << SKETCH_BITS];
+    seq_data.iter().for_each(|seq| {
+        for_each_kmer_hash(seq, k, |_, hash| {
+            sketch_slots(hash).iter().for_each(|slot| sketch[*slot] = sketch[*slot].saturating_add(1));
+        });
+    });
+
+    let min_count = min_count.min(u8::MAX as u32) as u8;
+    let mut res: Vec<Vec<u8>> = Vec::new();
+    seq_data.iter().for_each(|seq| {
+        // Start and end of the current run of solid k-mers
+        let mut run: Option<(usize, usize)> = None;
+        for_each_kmer_hash(seq, k, |pos, hash| {
+            let count = sketch_slots(hash).iter().map(|slot| sketch[*slot]).min().unwrap();
+            if count >= min_count {
+                run = match run {
+                    Some((start, end)) if end + 1 == pos => Some((start, pos)),
+                    Some((start, end)) => { res.push(seq[start..(end + k)].to_vec()); Some((pos, pos)) },
+                    None => Some((pos, pos)),
+                };
+            }
+        });
+        if let Some((start, end)) = run {
+            res.push(seq[start..(end + k)].to_vec());
+        }
+    });
+    res
+}
+
+/// Parses FASTA records or a bare sequence pasted in as text.
+pub async fn read_pasted_sequence(
+    text: &str,
+) -> Result<SeqData, ParseError> {
+    let text = text.trim();
+    let contents: Vec<u8> = if text.starts_with('>') || text.starts_with('@') {
+        text.as_bytes().to_vec()
+    } else {
+        // Bare sequences may be split over several lines
+        let seq: String = text.split_whitespace().collect();
+        (">pasted\n".to_string() + &seq + "\n").into_bytes()
     };
+    let mut reader = crate::reader::SeqReader::default();
+    reader.push(&contents)?;
+    Ok(SeqData { file_size: text.len(), ..reader.finish("pasted sequence")? })
+}
 
-    Ok(contigs)
+pub fn build_index(
+    input: &IndexInput,
+    build_opts: BuildOpts,
+) -> Result<IndexData, BuilderErr> {
+    let start = chrono::Local::now();
+    let filtered: Vec<Vec<u8>>;
+    let reads = if build_opts.min_kmer_count > 1 && !input.reads.is_empty() {
+        filtered = filter_by_kmer_count(&input.reads, build_opts.kmer_size as usize, build_opts.min_kmer_count);
+        if filtered.is_empty() && input.seqs.is_empty() {
+            return Err(BuilderErr::no_solid_kmers(&input.name, &build_opts))
+        }
+        &filtered
+    } else {
+        &input.reads
+    };
+    let combined: Vec<Vec<u8>>;
+    let seq_data = if reads.is_empty() {
+        &input.seqs
+    } else if input.seqs.is_empty() {
+        reads
+    } else {
+        combined = input.seqs.iter().chain(reads.iter()).cloned().collect();
+        &combined
+    };
+    let (sbwt, lcs) = crate::util::build_sbwt(seq_data, Some(build_opts.to_kbo()));
+    let build_time_ms = (chrono::Local::now() - start).num_milliseconds().max(0) as u64;
+    let bases: usize = seq_data.iter().map(|x| x.len()).sum();
+    let stats = IndexStats::new(&sbwt, &lcs);
+    Ok(IndexData { sbwt: Arc::new(sbwt), lcs: Arc::new(lcs), file_name: input.name.clone(), bases, build_opts, build_time_ms, stats })
 }
 
+// Finds the indexes cached under each of `keys` in the worker, which
+// drops the indexes stored under other keys from memory.
+async fn lookup_indexes(
+    keys: &[String],
+) -> Result<Vec<Option<Vec<IndexInfo>>>, BuilderErr> {
+    let job = crate::worker::Job::Lookup { keys: Cow::Borrowed(keys) };
+    match crate::worker::submit(&job, |_| {}).await {
+        Ok(crate::worker::JobOutput::Lookup(res)) => Ok(res),
+        Ok(_) => Err(BuilderErr{ code: 2, message: "Unexpected result from worker.".to_string() }),
+        Err(e) if e.is_cancelled() => Err(BuilderErr{ code: 3, message: e.message }),
+        Err(e) => Err(BuilderErr{ code: 2, message: "Looking up cached indexes failed: ".to_string() + &e.message }),
+    }
+}
+
+// Builds an index from each of `inputs` in the worker, which keeps
+// them and caches them under `key`.
 pub async fn sbwt_builder(
-    seq_data: &[Vec<u8>],
-    build_opts: kbo::BuildOpts,
-) -> Result<(sbwt::SbwtIndexVariant, sbwt::LcsArray), BuilderErr> {
-    Ok(crate::util::build_sbwt(seq_data, Some(build_opts)))
+    key: &str,
+    inputs: Vec<IndexInput>,
+    build_opts: BuildOpts,
+    on_progress: impl FnMut(Progress) + 'static,
+) -> Result<Vec<IndexInfo>, BuilderErr> {
+    let name = inputs.first().map(|x| x.name.clone()).unwrap_or_default();
+    let job = crate::worker::Job::Build { key: Cow::Borrowed(key), inputs: Cow::Owned(inputs), build_opts };
+    match crate::worker::submit(&job, on_progress).await {
+        Ok(crate::worker::JobOutput::Build(res)) => res,
+        Ok(_) => Err(BuilderErr{ code: 2, message: "Unexpected result from worker.".to_string() }),
+        Err(e) if e.is_cancelled() => Err(BuilderErr{ code: 3, message: e.message }),
+        Err(e) => Err(BuilderErr{ code: 2, message: "Indexing ".to_string() + &name + " failed: " + &e.message }),
+    }
+}
+
+// Prebuilt index files store the SBWT and LCS array of one or more
+// IndexData entries together with the metadata needed to use them.
+const INDEX_FILE_MAGIC: &[u8] = b"kbo-gui-index";
+// Version 2 added the build time, version 3 the read filters, version
+// 4 the soft-masking option and version 5 the contig length filter.
+const INDEX_FILE_VERSION: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexFileErr {
+    pub code: usize,
+    pub message: String,
+}
+
+impl From<std::io::Error> for IndexFileErr {
+    fn from(e: std::io::Error) -> IndexFileErr {
+        IndexFileErr{ code: 1, message: e.to_string() }
+    }
+}
+
+fn read_u32(input: &mut impl std::io::Read) -> std::io::Result<u32> {
+    let mut buf = [0_u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64(input: &mut impl std::io::Read) -> std::io::Result<u64> {
+    let mut buf = [0_u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+pub fn serialize_indexes(
+    indexes: &[IndexData],
+    out: &mut impl std::io::Write,
+) -> std::io::Result<()> {
+    out.write_all(INDEX_FILE_MAGIC)?;
+    out.write_all(&INDEX_FILE_VERSION.to_le_bytes())?;
+    out.write_all(&(indexes.len() as u64).to_le_bytes())?;
+    for index in indexes {
+        out.write_all(&index.build_opts.kmer_size.to_le_bytes())?;
+        out.write_all(&index.build_opts.prefix_precalc.to_le_bytes())?;
+        out.write_all(&[index.build_opts.dedup_batches as u8])?;
+        out.write_all(&[index.build_opts.min_base_quality])?;
+        out.write_all(&index.build_opts.min_kmer_count.to_le_bytes())?;
+        out.write_all(&[index.build_opts.exclude_soft_masked as u8])?;
+        out.write_all(&index.build_opts.min_contig_len.to_le_bytes())?;
+        out.write_all(&(index.file_name.len() as u64).to_le_bytes())?;
+        out.write_all(index.file_name.as_bytes())?;
+        out.write_all(&(index.bases as u64).to_le_bytes())?;
+        out.write_all(&index.build_time_ms.to_le_bytes())?;
+        sbwt::write_sbwt_index_variant(&index.sbwt, out)?;
+        index.lcs.serialize(out)?;
+    }
+    Ok(())
+}
+
+pub fn load_indexes(
+    input: &mut impl std::io::Read,
+) -> Result<Vec<IndexData>, IndexFileErr> {
+    let mut magic = vec![0_u8; INDEX_FILE_MAGIC.len()];
+    if input.read_exact(&mut magic).is_err() || magic != INDEX_FILE_MAGIC {
+        return Err(IndexFileErr{ code: 2, message: "Not a kbo-gui index file.".to_string() })
+    }
+    let version = read_u32(input)?;
+    if version == 0 || version > INDEX_FILE_VERSION {
+        return Err(IndexFileErr{ code: 3, message: "Unsupported index file version ".to_string() + &version.to_string() + "." })
+    }
+
+    let n_indexes = read_u64(input)? as usize;
+    let mut indexes: Vec<IndexData> = Vec::new();
+    for _ in 0..n_indexes {
+        let kmer_size = read_u32(input)?;
+        let prefix_precalc = read_u32(input)?;
+        let mut dedup_batches = [0_u8; 1];
+        input.read_exact(&mut dedup_batches)?;
+        let mut build_opts = BuildOpts { kmer_size, prefix_precalc, dedup_batches: dedup_batches[0] != 0, ..Default::default() };
+        if version >= 3 {
+            let mut min_base_quality = [0_u8; 1];
+            input.read_exact(&mut min_base_quality)?;
+            build_opts.min_base_quality = min_base_quality[0];
+            build_opts.min_kmer_count = read_u32(input)?;
+        }
+        if version >= 4 {
+            let mut exclude_soft_masked = [0_u8; 1];
+            input.read_exact(&mut exclude_soft_masked)?;
+            build_opts.exclude_soft_masked = exclude_soft_masked[0] != 0;
+        }
+        if version >= 5 {
+            build_opts.min_contig_len = read_u64(input)?;
+        }
+
+        let name_len = read_u64(input)? as usize;
+        let mut file_name = vec![0_u8; name_len];
+        input.read_exact(&mut file_name)?;
+        let file_name = String::from_utf8(file_name).map_err(|_| IndexFileErr{ code: 4, message: "Index file name is not valid UTF-8.".to_string() })?;
+        let bases = read_u64(input)? as usize;
+        let build_time_ms = if version >= 2 { read_u64(input)? } else { 0 };
+
+        let sbwt = sbwt::load_sbwt_index_variant(input).map_err(|e| IndexFileErr{ code: 5, message: e.to_string() })?;
+        let lcs = sbwt::LcsArray::load(input)?;
+        let stats = IndexStats::new(&sbwt, &lcs);
+        indexes.push(IndexData { sbwt: Arc::new(sbwt), lcs: Arc::new(lcs), file_name, bases, build_opts, build_time_ms, stats });
+    }
+
+    if indexes.is_empty() {
+        return Err(IndexFileErr{ code: 0, message: "Index file contains no indexes.".to_string() })
+    }
+    Ok(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_and_load_indexes() {
+        let seq: Vec<u8> = b"AAAGAACCATTCAGGGCGTTTAGGCAACCTTAGCATCGGATACCTAG".to_vec();
+        let build_opts = BuildOpts { kmer_size: 9, prefix_precalc: 4, dedup_batches: false, exclude_soft_masked: true, min_contig_len: 20, ..Default::default() };
+        let (sbwt, lcs) = build_sbwt(std::slice::from_ref(&seq), Some(build_opts.to_kbo()));
+        let stats = IndexStats::new(&sbwt, &lcs);
+        let expected = IndexData { sbwt: Arc::new(sbwt), lcs: Arc::new(lcs), file_name: "test.fasta".to_string(), bases: seq.len(), build_opts, build_time_ms: 12, stats };
+
+        let mut bytes: Vec<u8> = Vec::new();
+        serialize_indexes(std::slice::from_ref(&expected), &mut bytes).unwrap();
+        let got = load_indexes(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(got.len(), 1);
+        assert_eq!(got[0].file_name, expected.file_name);
+        assert_eq!(got[0].bases, expected.bases);
+        assert_eq!(got[0].build_time_ms, expected.build_time_ms);
+        assert!(got[0].build_opts == expected.build_opts);
+        assert!(got[0].stats == expected.stats);
+        assert_eq!(got[0].lcs.len(), expected.lcs.len());
+        let (sbwt::SbwtIndexVariant::SubsetMatrix(got_sbwt), sbwt::SbwtIndexVariant::SubsetMatrix(expected_sbwt)) = (&*got[0].sbwt, &*expected.sbwt);
+        assert_eq!(got_sbwt, expected_sbwt);
+    }
+
+    #[test]
+    fn validate_build_input_names_short_contig() {
+        let build_opts = BuildOpts { kmer_size: 9, ..Default::default() };
+        let seq_data = SeqData {
+            contigs: vec![
+                Arc::new(ContigData { name: "long".to_string(), seq: b"AAAGAACCATTCAGGG".to_vec(), qual: Vec::new(), features: Vec::new(), soft_masked: Vec::new() }),
+                Arc::new(ContigData { name: "short".to_string(), seq: b"ACGT".to_vec(), qual: Vec::new(), features: Vec::new(), soft_masked: Vec::new() }),
+            ],
+            file_name: "test.fasta".to_string(),
+            file_size: 0,
+            skipped_records: 0,
+            sample: None,
+            non_nucleotide: Vec::new(),
+        };
+
+        assert!(validate_build_input(&seq_data, false, &build_opts).is_ok());
+        let got = validate_build_input(&seq_data, true, &build_opts).err().unwrap();
+        assert_eq!(got.code, 7);
+        assert!(got.message.contains("short") && got.message.contains("test.fasta"));
+
+        // Contigs dropped by the length filter aren't checked against k
+        let build_opts = BuildOpts { kmer_size: 9, min_contig_len: 10, ..Default::default() };
+        assert!(validate_build_input(&seq_data, true, &build_opts).is_ok());
+        let build_opts = BuildOpts { kmer_size: 9, min_contig_len: 20, ..Default::default() };
+        let got = validate_build_input(&seq_data, false, &build_opts).err().unwrap();
+        assert_eq!(got.code, 8);
+        assert!(got.message.contains("test.fasta"));
+    }
+
+    #[test]
+    fn prepare_queries_masks_and_splits() {
+        let contig = ContigData {
+            name: "contig1 description".to_string(),
+            seq: b"NNACGTNNNNAACCNGGTTNNNN".to_vec(),
+            qual: Vec::new(),
+            features: Vec::new(),
+            soft_masked: vec![(2, 4)],
+        };
+        let seq_data = vec![SeqData { contigs: vec![Arc::new(contig)], ..Default::default() }];
+
+        let build_opts = BuildOpts { exclude_soft_masked: true, ..Default::default() };
+        let got = prepare_queries(&seq_data, &build_opts, 0);
+        assert_eq!(got[0].contigs[0].seq, b"NNNNGTNNNNAACCNGGTTNNNN");
+
+        let got = prepare_queries(&seq_data, &BuildOpts::default(), 1);
+        let names: Vec<&str> = got[0].contigs.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["contig1:3-6", "contig1:11-19"]);
+        assert_eq!(got[0].contigs[1].seq, b"AACCNGGTT");
+
+        let mut seq_data = seq_data;
+        seq_data[0].contigs.push(Arc::new(ContigData { name: "short".to_string(), seq: b"ACGT".to_vec(), qual: Vec::new(), features: Vec::new(), soft_masked: Vec::new() }));
+        let build_opts = BuildOpts { min_contig_len: 10, ..Default::default() };
+        let got = prepare_queries(&seq_data, &build_opts, 0);
+        let names: Vec<&str> = got[0].contigs.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["contig1 description"]);
+    }
+
+    #[test]
+    fn filter_by_kmer_count_splits_at_rare_kmers() {
+        let seq = b"AAAGAACCATTCAGGGCGTTTAGGCAACCTTAGCATCGGATACCTAG".to_vec();
+        // Both strands count towards the same k-mer
+        let rev: Vec<u8> = seq.iter().rev().map(|x| complement(*x)).collect();
+        let mut error = seq.clone();
+        error[20] = b'C';
+        let got = filter_by_kmer_count(&[seq.clone(), rev.clone(), error], 9, 2);
+        assert_eq!(got.len(), 4);
+        assert_eq!(got[0], seq);
+        assert_eq!(got[1], rev);
+        assert_eq!(got[2], seq[..20].to_vec());
+        assert_eq!(got[3], seq[21..].to_vec());
+
+        let build_opts = BuildOpts { kmer_size: 9, min_kmer_count: 2, ..Default::default() };
+        let input = IndexInput { name: "a.fastq".to_string(), reads: vec![seq], ..Default::default() };
+        assert_eq!(build_index(&input, build_opts).err().unwrap().code, 9);
+    }
+
+    #[test]
+    fn read_filters_skip_records_without_qualities() {
+        let contig = |name: &str, qual: &[u8]| Arc::new(ContigData {
+            name: name.to_string(),
+            seq: b"AAAGAACCATTCAGGGCGTTTAGG".to_vec(),
+            qual: qual.to_vec(),
+            features: Vec::new(),
+            soft_masked: Vec::new(),
+        });
+        let build_opts = BuildOpts { kmer_size: 9, min_base_quality: 20, min_kmer_count: 2, ..Default::default() };
+
+        // The FASTA record keeps all of its bases and single-copy k-mers
+        let mut input = IndexInput::default();
+        let fasta = contig("contig", b"");
+        let fastq = contig("read", &[b'!'; 24]);
+        input.push(&fasta, &build_opts);
+        input.push(&fastq, &build_opts);
+        assert_eq!(input.seqs, vec![fasta.seq.clone()]);
+        assert_eq!(input.reads, vec![vec![b'N'; 24]]);
+
+        // Reads without solid k-mers don't fail a mixed build
+        let got = build_index(&input, build_opts).unwrap();
+        assert_eq!(got.bases, fasta.seq.len());
+        let expected = build_index(&IndexInput { seqs: vec![fasta.seq.clone()], ..Default::default() }, build_opts).unwrap();
+        assert_eq!(got.stats.n_kmers, expected.stats.n_kmers);
+    }
+
+    #[test]
+    fn load_indexes_rejects_other_files() {
+        let bytes = b">contig\nACGT\n".to_vec();
+        let got = load_indexes(&mut bytes.as_slice());
+        assert_eq!(got.err().unwrap().code, 2);
+    }
 }
@@ -11,21 +11,27 @@
 // the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
 // at your option.
 //
+use std::sync::Arc;
+
 use dioxus::prelude::*;
 
+use crate::components::cache::*;
 use crate::components::common::*;
+use crate::components::stats::*;
 use crate::components::call::*;
 use crate::components::find::*;
 use crate::components::map::*;
 
 use crate::common::*;
 
-use crate::opts::GuiOpts;
+use crate::opts::{AlnOpts, BuildOpts, GuiOpts};
+use crate::samplesheet::SampleSheet;
+use crate::worker::Progress;
 
 static CSS: Asset = asset!("/assets/main.css");
 
 struct ResultCache {
-    pub call: Signal<Result<CallResults, CallRunnerErr>>,
+    pub call: Signal<Result<Vec<CallResults>, CallRunnerErr>>,
     pub find: Signal<Result<Vec<FindResult>, FindRunnerErr>>,
     pub map: Signal<Result<Vec<MapResult>, MapRunnerErr>>,
 }
@@ -42,20 +48,71 @@ impl Default for ResultCache {
 
 #[component]
 pub fn Kbo() -> Element {
-    // Input data
+    // Input files as added, removed and reordered in the selectors
+    let staged_reference: Signal<Vec<SeqData>> = use_signal(Vec::new);
+    let staged_queries: Signal<Vec<SeqData>> = use_signal(Vec::new);
+
+    // Input data applied by the Run button
     let reference: Signal<Vec<SeqData>> = use_signal(Vec::new);
     let queries: Signal<Vec<SeqData>> = use_signal(Vec::new);
 
+    // Sample IDs of the input files
+    let sample_sheet: Signal<Option<SampleSheet>> = use_signal(|| None);
+
+    // Reference contigs left out of the runs, as (file, contig) names,
+    // as ticked in the selector and as applied by the Run button
+    let staged_excluded: Signal<Vec<(String, String)>> = use_signal(Vec::new);
+    let excluded_contigs: Signal<Vec<(String, String)>> = use_signal(Vec::new);
+    let selected_reference: Memo<Vec<SeqData>> = use_memo(move || {
+        let excluded = excluded_contigs.read();
+        reference.read().iter().filter_map(|file| {
+            let contigs: Vec<Arc<ContigData>> = file.contigs.iter().filter(|contig| {
+                !excluded.contains(&(file.file_name.clone(), contig.name.clone()))
+            }).cloned().collect();
+            if contigs.is_empty() {
+                None
+            } else {
+                Some(SeqData { contigs, file_name: file.file_name.clone(), file_size: file.file_size, skipped_records: file.skipped_records, sample: file.sample.clone(), non_nucleotide: file.non_nucleotide.clone() })
+            }
+        }).collect()
+    });
+
     // Cached SBWT
-    let index: Signal<Vec<IndexData>> = use_signal(Vec::new);
+    let index: Signal<Vec<IndexInfo>> = use_signal(Vec::new);
 
     // Options
     let kbo_mode: Signal<KboMode> = use_signal(KboMode::default);
     let gui_opts: Signal<GuiOpts> = use_signal(GuiOpts::default);
 
+    // Options applied by the Run button
+    let run_opts: Signal<GuiOpts> = use_signal(GuiOpts::default);
+
+    // Split the applied options so that the index is only rebuilt
+    // when the options it depends on change, and the runners are
+    // not rerun by changes that only affect rendering.
+    let build_opts: Memo<BuildOpts> = use_memo(move || run_opts.read().build_opts);
+    let detailed: Memo<bool> = use_memo(move || run_opts.read().out_opts.detailed);
+    let aln_opts: Memo<GuiOpts> = use_memo(move || {
+        let opts = run_opts.read().aln_opts;
+        // Keep only the options the runner of the current mode uses,
+        // the others are left at their defaults
+        let aln_opts = match *kbo_mode.read() {
+            KboMode::Call => AlnOpts { max_error_prob: opts.max_error_prob, ..Default::default() },
+            KboMode::Find => AlnOpts { max_error_prob: opts.max_error_prob, max_gap_len: opts.max_gap_len, min_n_run: opts.min_n_run, ..Default::default() },
+            KboMode::Map => AlnOpts { max_error_prob: opts.max_error_prob, do_vc: opts.do_vc, do_gapfill: opts.do_gapfill, ..Default::default() },
+        };
+        GuiOpts { aln_opts, ..Default::default() }
+    });
+
+    // Build options that the staged files would be indexed with
+    let staged_build_opts: Memo<BuildOpts> = use_memo(move || gui_opts.read().build_opts);
+
     // Cached results
     let results: ResultCache = ResultCache::default();
 
+    // Status of the running job
+    let progress: Signal<Option<Progress>> = use_signal(|| None);
+
     rsx! {
         document::Stylesheet { href: CSS }
 
@@ -69,9 +126,17 @@ pub fn Kbo() -> Element {
               div { class: "row",
                     div { class: "column-left",
                           div { class: "row",
-                                strong { "Reference file" },
+                                strong { "Reference file(s)" },
                           }
-                          FastaFileSelector { multiple: false, out_data: reference },
+                          FastaFileSelector { multiple: true, reads: false, annotated: true, out_data: staged_reference, sample_sheet },
+                          ShortContigs { seq_data: staged_reference, build_opts: staged_build_opts },
+
+                          div { class: "row",
+                                details {
+                                    summary { "Reference contigs" },
+                                    ContigSelector { seq_data: staged_reference, excluded: staged_excluded },
+                                }
+                          },
 
                           div { class: "row",
                                 details {
@@ -80,6 +145,13 @@ pub fn Kbo() -> Element {
                                 }
                           },
 
+                          div { class: "row",
+                                details {
+                                    summary { "Index cache" },
+                                    IndexCacheManager { cached_index: index },
+                                }
+                          },
+
                           div { class: "row-contents",
                                 InteractivitySwitcher { kbo_mode, opts: gui_opts },
                           },
@@ -89,7 +161,30 @@ pub fn Kbo() -> Element {
                           div { class: "row",
                                 strong { { "Query file".to_string() + if *kbo_mode.read() != KboMode::Call { "(s)" } else { "" } } }
                           }
-                          FastaFileSelector { multiple: *kbo_mode.read() != KboMode::Call, out_data: queries },
+                          FastaFileSelector { multiple: *kbo_mode.read() != KboMode::Call, reads: true, annotated: false, out_data: staged_queries, sample_sheet },
+                          ShortContigs { seq_data: staged_queries, build_opts: staged_build_opts },
+
+                          div { class: "row",
+                                details {
+                                    summary { "Prebuilt index" },
+                                    IndexFileSelector { staged_seq_data: staged_queries, seq_data: queries, gui_opts, run_opts, cached_index: index },
+                                    IndexDownloader { index },
+                                }
+                          },
+
+                          div { class: "row",
+                                details {
+                                    summary { "Sample sheet" },
+                                    SampleSheetSelector { sample_sheet, reference: staged_reference, queries: staged_queries },
+                                }
+                          },
+
+                          div { class: "row",
+                                details {
+                                    summary { "Index statistics" },
+                                    IndexStatistics { index },
+                                }
+                          },
 
                           div { class: "row",
                                 details {
@@ -108,27 +203,31 @@ pub fn Kbo() -> Element {
                     }
               }
 
+              div { class: "row",
+                    RunButton { staged_opts: gui_opts, run_opts, staged_reference, reference, staged_queries, queries, staged_excluded, excluded: excluded_contigs },
+              }
+
               // Dynamically rendered components,
               // based on which KboMode is selected.
               div { class: "row-results",
                     SuspenseBoundary {
-                        fallback: |_| rsx! {
-                            span { class: "loader" },
+                        fallback: move |_| rsx! {
+                            JobStatus { progress, reference, queries },
                         },
 
                         // Build index
-                        IndexBuilder { seq_data: queries, gui_opts, cached_index: index }
+                        IndexBuilder { seq_data: queries, build_opts, detailed, cached_index: index, progress }
 
                         // Run commands
                         match *kbo_mode.read() {
                             KboMode::Call => {
-                                rsx!{ Call { ref_contigs: reference, index: index, opts: gui_opts, result: results.call } }
+                                rsx!{ Call { ref_contigs: selected_reference, index: index, opts: aln_opts, result: results.call, progress } }
                             },
                             KboMode::Find => {
-                                rsx! { Find { indexes: index, query_contigs: reference, opts: gui_opts, result: results.find } }
+                                rsx! { Find { indexes: index, query_contigs: selected_reference, opts: aln_opts, result: results.find, progress } }
                             },
                             KboMode::Map => {
-                                rsx! { Map { ref_contigs: reference, indexes: index, opts: gui_opts, result: results.map } }
+                                rsx! { Map { ref_contigs: selected_reference, indexes: index, opts: aln_opts, result: results.map, progress } }
                             },
                         }
                     }
@@ -137,13 +236,13 @@ pub fn Kbo() -> Element {
                     // Render results
                     match *kbo_mode.read() {
                         KboMode::Call => {
-                            rsx! { CallRenderer { result: results.call, opts: gui_opts } }
+                            rsx! { CallRenderer { result: results.call, opts: run_opts } }
                         },
                         KboMode::Find => {
-                            rsx! { FindRenderer { result: results.find, opts: gui_opts } }
+                            rsx! { FindRenderer { result: results.find, opts: run_opts } }
                         },
                         KboMode::Map => {
-                            rsx! { MapRenderer { result: results.map, opts: gui_opts } }
+                            rsx! { MapRenderer { result: results.map, opts: run_opts } }
                         },
                     }
               }
@@ -0,0 +1,670 @@
+// kbo-gui: Graphical user interface for kbo built with Dioxus.
+//
+// Copyright 2024 Tommi Mäklin [tommi@maklin.fi].
+
+// Copyrights in this project are retained by contributors. No copyright assignment
+// is required to contribute to this project.
+
+// Except as otherwise noted (below and/or in individual files), this
+// project is licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE> or <http://www.apache.org/licenses/LICENSE-2.0> or
+// the MIT license, <LICENSE-MIT> or <http://opensource.org/licenses/MIT>,
+// at your option.
+//
+// Execution backend for index building and alignment. In the browser jobs
+// are sent to a Web Worker running a second instance of this app so the
+// UI thread stays responsive; on native targets they run inline.
+//
+use std::borrow::Cow;
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{CacheEntry, CacheErr};
+use crate::common::*;
+use crate::components::call::*;
+use crate::components::find::*;
+use crate::components::map::*;
+use crate::opts::{BuildOpts, GuiOpts};
+use crate::util::{BuilderErr, IndexFileErr, IndexInput};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerErr {
+    pub code: usize,
+    pub message: String,
+}
+
+impl WorkerErr {
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    pub fn cancelled() -> WorkerErr {
+        WorkerErr{ code: 3, message: "Cancelled.".to_string() }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.code == 3
+    }
+
+    // The worker was restarted to stop another job, the job is sent again
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+    fn restarted() -> WorkerErr {
+        WorkerErr{ code: 4, message: "Worker was restarted.".to_string() }
+    }
+}
+
+/// Reports that a job is on step `step` of `total`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub message: String,
+    pub step: usize,
+    pub total: usize,
+}
+
+impl Progress {
+    pub fn new(message: &str, step: usize, total: usize) -> Progress {
+        Progress { message: message.to_string(), step, total }
+    }
+}
+
+// Jobs refer to the indexes kept in the worker by their [IndexInfo], so
+// only the inputs and results are passed between the threads.
+#[derive(Serialize, Deserialize)]
+pub enum Job<'a> {
+    /// Finds the indexes cached under each of `keys` and drops the other
+    /// indexes from memory.
+    Lookup {
+        keys: Cow<'a, [String]>,
+    },
+    /// Builds an index from each of `inputs` and caches them under `key`.
+    Build {
+        key: Cow<'a, str>,
+        inputs: Cow<'a, [IndexInput]>,
+        build_opts: BuildOpts,
+    },
+    /// Reads a prebuilt index file.
+    Load {
+        #[serde(serialize_with = "serde_bytes::serialize", deserialize_with = "owned_bytes")]
+        contents: Cow<'a, [u8]>,
+    },
+    /// Writes `indexes` in the prebuilt index file format.
+    Export {
+        indexes: Cow<'a, [IndexInfo]>,
+    },
+    /// Lists the cached indexes.
+    ListCache,
+    /// Removes the indexes cached under `key`.
+    EvictCache {
+        key: Cow<'a, str>,
+    },
+    /// Removes all cached indexes.
+    ClearCache,
+    Call {
+        reference: Cow<'a, [SeqData]>,
+        index: Cow<'a, IndexInfo>,
+        opts: GuiOpts,
+    },
+    Find {
+        indexes: Cow<'a, [IndexInfo]>,
+        queries: Cow<'a, [SeqData]>,
+        ref_file: Cow<'a, str>,
+        opts: GuiOpts,
+    },
+    Map {
+        reference: Cow<'a, [SeqData]>,
+        indexes: Cow<'a, [IndexInfo]>,
+        opts: GuiOpts,
+    },
+}
+
+// Jobs are deserialized from a message that is dropped before they run
+fn owned_bytes<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Cow<'static, [u8]>, D::Error> {
+    serde_bytes::ByteBuf::deserialize(deserializer).map(|x| Cow::Owned(x.into_vec()))
+}
+
+impl Job<'_> {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    fn to_owned(&self) -> Job<'static> {
+        fn owned<T: ToOwned + ?Sized>(x: &T) -> Cow<'static, T> where T::Owned: 'static {
+            Cow::Owned(x.to_owned())
+        }
+        match self {
+            Job::Lookup { keys } => Job::Lookup { keys: owned(keys) },
+            Job::Build { key, inputs, build_opts } => Job::Build { key: owned(key), inputs: owned(inputs), build_opts: *build_opts },
+            Job::Load { contents } => Job::Load { contents: owned(contents) },
+            Job::Export { indexes } => Job::Export { indexes: owned(indexes) },
+            Job::ListCache => Job::ListCache,
+            Job::EvictCache { key } => Job::EvictCache { key: owned(key) },
+            Job::ClearCache => Job::ClearCache,
+            Job::Call { reference, index, opts } => Job::Call { reference: owned(reference), index: owned(index), opts: *opts },
+            Job::Find { indexes, queries, ref_file, opts } => Job::Find { indexes: owned(indexes), queries: owned(queries), ref_file: owned(ref_file), opts: *opts },
+            Job::Map { reference, indexes, opts } => Job::Map { reference: owned(reference), indexes: owned(indexes), opts: *opts },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum JobOutput {
+    Lookup(Vec<Option<Vec<IndexInfo>>>),
+    Build(Result<Vec<IndexInfo>, BuilderErr>),
+    Load(Result<Vec<IndexInfo>, IndexFileErr>),
+    Export(Result<serde_bytes::ByteBuf, IndexFileErr>),
+    ListCache(Result<Vec<CacheEntry>, CacheErr>),
+    UpdateCache(Result<(), CacheErr>),
+    Call(Result<Vec<CallResults>, CallRunnerErr>),
+    Find(Result<Vec<FindResult>, FindRunnerErr>),
+    Map(Result<Vec<MapResult>, MapRunnerErr>),
+}
+
+fn index_infos(key: &str, indexes: &[IndexData]) -> Vec<IndexInfo> {
+    indexes.iter().enumerate().map(|(pos, index)| index.info(key, pos)).collect()
+}
+
+// Finds the indexes that `infos` refer to. Indexes that were dropped
+// from memory, for example when the worker was restarted, are read
+// back from the cache.
+async fn resolve(infos: &[IndexInfo]) -> Result<Vec<IndexData>, String> {
+    let mut indexes: Vec<IndexData> = Vec::with_capacity(infos.len());
+    for info in infos {
+        let index = crate::cache::get(&info.key).await.and_then(|mut x| (info.pos < x.len()).then(|| x.swap_remove(info.pos)));
+        match index {
+            Some(index) => indexes.push(IndexData { file_name: info.file_name.clone(), ..index }),
+            None => return Err("Index of ".to_string() + &info.file_name + " is no longer available, add the file again to rebuild it."),
+        }
+    }
+    Ok(indexes)
+}
+
+/// Runs `job` on the current thread.
+pub async fn execute(
+    job: &Job<'_>,
+    progress: &mut dyn FnMut(Progress),
+) -> JobOutput {
+    match job {
+        Job::Lookup { keys } => {
+            crate::cache::retain_loaded(keys);
+            let mut found: Vec<Option<Vec<IndexInfo>>> = Vec::with_capacity(keys.len());
+            for key in keys.iter() {
+                found.push(crate::cache::get(key).await.map(|indexes| index_infos(key, &indexes)));
+            }
+            JobOutput::Lookup(found)
+        },
+        Job::Build { key, inputs, build_opts } => {
+            let mut indexes: Vec<IndexData> = Vec::with_capacity(inputs.len());
+            for (i, input) in inputs.iter().enumerate() {
+                if inputs.len() > 1 {
+                    progress(Progress::new("Indexing contig", i + 1, inputs.len()));
+                }
+                match crate::util::build_index(input, *build_opts) {
+                    Ok(index) => indexes.push(index),
+                    Err(e) => return JobOutput::Build(Err(e)),
+                }
+            }
+            let infos = index_infos(key, &indexes);
+            let _ = crate::cache::put(key, indexes).await;
+            JobOutput::Build(Ok(infos))
+        },
+        Job::Load { contents } => {
+            let indexes = match crate::util::load_indexes(&mut contents.as_ref()) {
+                Ok(indexes) => indexes,
+                Err(e) => return JobOutput::Load(Err(e)),
+            };
+            // Loaded files are cached like built indexes so that they
+            // can be found again if the worker is restarted
+            let key = crate::cache::file_key(contents);
+            let infos = index_infos(&key, &indexes);
+            let _ = crate::cache::put_serialized(&key, indexes, contents).await;
+            JobOutput::Load(Ok(infos))
+        },
+        Job::Export { indexes } => {
+            let res = match resolve(indexes).await {
+                Ok(indexes) => {
+                    let mut bytes: Vec<u8> = Vec::new();
+                    crate::util::serialize_indexes(&indexes, &mut bytes)
+                        .map(|_| serde_bytes::ByteBuf::from(bytes))
+                        .map_err(|e| IndexFileErr{ code: 1, message: e.to_string() })
+                },
+                Err(message) => Err(IndexFileErr{ code: 0, message }),
+            };
+            JobOutput::Export(res)
+        },
+        Job::ListCache => JobOutput::ListCache(crate::cache::list().await),
+        Job::EvictCache { key } => JobOutput::UpdateCache(crate::cache::evict(key).await),
+        Job::ClearCache => JobOutput::UpdateCache(crate::cache::clear().await),
+        Job::Call { reference, index, opts } => {
+            let index = match resolve(std::slice::from_ref(index)).await {
+                Ok(mut indexes) => indexes.remove(0),
+                Err(message) => return JobOutput::Call(Err(CallRunnerErr{ code: 0, message })),
+            };
+            // Calls and their REF alleles come from the unmasked reference,
+            // soft-masked bases are only left out of the index
+            let build_opts = BuildOpts { exclude_soft_masked: false, ..opts.build_opts };
+            let reference = crate::util::prepare_queries(reference, &build_opts, 0);
+            JobOutput::Call(call_runner(&reference, &index, opts.to_kbo_call(), progress))
+        },
+        Job::Find { indexes, queries, ref_file, opts } => {
+            let indexes = match resolve(indexes).await {
+                Ok(indexes) => indexes,
+                Err(message) => return JobOutput::Find(Err(FindRunnerErr{ code: 2, message })),
+            };
+            let queries = crate::util::prepare_queries(queries, &opts.build_opts, opts.aln_opts.min_n_run as usize);
+            JobOutput::Find(find_runner(&indexes, &queries, ref_file, opts.to_kbo_find(), progress))
+        },
+        Job::Map { reference, indexes, opts } => {
+            let indexes = match resolve(indexes).await {
+                Ok(indexes) => indexes,
+                Err(message) => return JobOutput::Map(Err(MapRunnerErr{ code: 0, message })),
+            };
+            // The mapped sequence is given in reference coordinates and bases
+            let build_opts = BuildOpts { exclude_soft_masked: false, ..opts.build_opts };
+            let reference = crate::util::prepare_queries(reference, &build_opts, 0);
+            JobOutput::Map(map_runner(&reference, &indexes, opts.to_kbo_map(), progress))
+        },
+    }
+}
+
+/// Sends `job` to the execution backend, calling `on_progress` with
+/// any progress the job reports.
+///
+/// The inputs are copied out of `job` before this returns, so the returned
+/// future does not hold on to any borrowed data (or signal guards).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn submit(
+    job: &Job,
+    mut on_progress: impl FnMut(Progress) + 'static,
+) -> impl Future<Output = Result<JobOutput, WorkerErr>> {
+    let job = job.to_owned();
+    async move { Ok(execute(&job, &mut on_progress).await) }
+}
+
+/// Jobs run to completion on native targets.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn cancel() {}
+
+#[cfg(target_arch = "wasm32")]
+pub use backend::{cancel, is_worker, start_worker, submit};
+
+#[cfg(target_arch = "wasm32")]
+mod backend {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::collections::{HashMap, VecDeque};
+    use std::rc::Rc;
+
+    use futures_channel::oneshot;
+    use js_sys::{Array, Function, Promise, Uint8Array};
+    use wasm_bindgen::prelude::*;
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+    use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, Worker};
+
+    const READY: &str = "ready";
+    const CANCELLED: &str = "cancelled";
+    const RESTARTED: &str = "restarted";
+
+    #[wasm_bindgen(inline_js = r#"
+export function glue_url() {
+    for (const script of document.querySelectorAll('script[type="module"]')) {
+        const found = script.textContent.match(/import\s+init\s+from\s+["']([^"']+\.js)["']/);
+        if (found) { return new URL(found[1], document.baseURI).href; }
+    }
+    const link = document.querySelector('link[rel="modulepreload"][href$=".js"]');
+    if (link) { return link.href; }
+    // wasm-bindgen places inline snippets two levels below the main module
+    return new URL("../../kbo-gui.js", import.meta.url).href;
+}
+"#)]
+    extern "C" {
+        fn glue_url() -> String;
+    }
+
+    impl From<JsValue> for WorkerErr {
+        fn from(e: JsValue) -> WorkerErr {
+            WorkerErr{ code: 1, message: e.as_string().unwrap_or_else(|| format!("{:?}", e)) }
+        }
+    }
+
+    impl From<bincode::Error> for WorkerErr {
+        fn from(e: bincode::Error) -> WorkerErr {
+            WorkerErr{ code: 2, message: e.to_string() }
+        }
+    }
+
+    // Messages from the worker, prefixed with the job id. Messages to
+    // the worker are the id followed by the job, or only the id to
+    // cancel a job that hasn't started yet.
+    #[derive(Serialize, Deserialize)]
+    enum Reply {
+        Started,
+        Progress(Progress),
+        Done(JobOutput),
+        Malformed(String),
+    }
+
+    struct PendingJob {
+        sender: oneshot::Sender<Result<JobOutput, WorkerErr>>,
+        on_progress: Rc<RefCell<dyn FnMut(Progress)>>,
+        started: bool,
+    }
+
+    struct Backend {
+        worker: Worker,
+        ready: Promise,
+        ready_reject: Function,
+        pending: Rc<RefCell<HashMap<u64, PendingJob>>>,
+        next_id: Cell<u64>,
+    }
+
+    impl Backend {
+        fn stop(&self, error: WorkerErr) {
+            self.worker.terminate();
+            let reason = if error.is_cancelled() {
+                CANCELLED
+            } else if error == WorkerErr::restarted() {
+                RESTARTED
+            } else {
+                &error.message
+            };
+            let _ = self.ready_reject.call1(&JsValue::NULL, &JsValue::from_str(reason));
+            self.pending.borrow_mut().drain().for_each(|(_, job)| { let _ = job.sender.send(Err(error.clone())); });
+        }
+    }
+
+    enum State {
+        Idle,
+        Running(Rc<Backend>),
+        // The worker could not be started, run jobs inline instead.
+        Unavailable,
+    }
+
+    thread_local! {
+        static STATE: RefCell<State> = const { RefCell::new(State::Idle) };
+        // Jobs waiting in the worker, and whether they are being served
+        static QUEUE: RefCell<VecDeque<([u8; 8], Job<'static>)>> = const { RefCell::new(VecDeque::new()) };
+        static SERVING: Cell<bool> = const { Cell::new(false) };
+    }
+
+    /// True if running inside the Web Worker started by [submit].
+    pub fn is_worker() -> bool {
+        js_sys::global().dyn_into::<DedicatedWorkerGlobalScope>().is_ok()
+    }
+
+    fn post(
+        scope: &DedicatedWorkerGlobalScope,
+        id: &[u8],
+        reply: &Reply,
+    ) {
+        let mut message: Vec<u8> = id.to_vec();
+        if bincode::serialize_into(&mut message, reply).is_ok() {
+            let message = Uint8Array::from(message.as_slice());
+            let _ = scope.post_message_with_transfer(&message, &Array::of1(&message.buffer()));
+        }
+    }
+
+    // Runs the queued jobs one at a time. Each job waits for a timer so
+    // that cancel messages sent meanwhile are handled before it starts.
+    async fn serve(scope: DedicatedWorkerGlobalScope) {
+        loop {
+            gloo_timers::future::TimeoutFuture::new(0).await;
+            let Some((id, job)) = QUEUE.with(|queue| queue.borrow_mut().pop_front()) else { break };
+            post(&scope, &id, &Reply::Started);
+
+            // Report at most once per percent to avoid flooding the UI thread
+            let mut last_percent = usize::MAX;
+            let mut on_progress = |progress: Progress| {
+                let percent = progress.step * 100 / progress.total.max(1);
+                if percent != last_percent {
+                    last_percent = percent;
+                    post(&scope, &id, &Reply::Progress(progress));
+                }
+            };
+            let output = execute(&job, &mut on_progress).await;
+            post(&scope, &id, &Reply::Done(output));
+        }
+        SERVING.with(|serving| serving.set(false));
+    }
+
+    /// Serves jobs posted from the UI thread. Called from `main` when
+    /// the app is instantiated inside the worker.
+    pub fn start_worker() {
+        let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+        let reply_to = scope.clone();
+        let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let message = Uint8Array::new(&event.data()).to_vec();
+            let (id, payload) = message.split_at(8);
+            let id: [u8; 8] = id.try_into().unwrap();
+
+            if payload.is_empty() {
+                QUEUE.with(|queue| queue.borrow_mut().retain(|(queued, _)| *queued != id));
+                return
+            }
+            match bincode::deserialize::<Job<'static>>(payload) {
+                Ok(job) => QUEUE.with(|queue| queue.borrow_mut().push_back((id, job))),
+                Err(e) => {
+                    post(&reply_to, &id, &Reply::Malformed(e.to_string()));
+                    return
+                },
+            }
+            if !SERVING.with(|serving| serving.replace(true)) {
+                wasm_bindgen_futures::spawn_local(serve(reply_to.clone()));
+            }
+        });
+        scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+        let _ = scope.post_message(&JsValue::from_str(READY));
+    }
+
+    fn spawn() -> Result<Rc<Backend>, WorkerErr> {
+        // The worker imports the same bundle as the UI thread and
+        // instantiates it from the already compiled module.
+        let script = "import init from \"".to_string() + &glue_url() + "\";\n" +
+            "self.onmessage = (event) => {\n" +
+            "    self.onmessage = null;\n" +
+            "    // Rethrow so that failures reach the Worker's onerror\n" +
+            "    init({ module_or_path: event.data }).catch((e) => setTimeout(() => { throw e; }));\n" +
+            "};\n";
+        let options = web_sys::BlobPropertyBag::new();
+        options.set_type("text/javascript");
+        let blob = web_sys::Blob::new_with_str_sequence_and_options(&Array::of1(&script.into()), &options)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+        let worker_options = web_sys::WorkerOptions::new();
+        worker_options.set_type(web_sys::WorkerType::Module);
+        let worker = Worker::new_with_options(&url, &worker_options)?;
+
+        let mut ready_callbacks: Option<(Function, Function)> = None;
+        let ready = Promise::new(&mut |resolve, reject| { ready_callbacks = Some((resolve, reject)); });
+        let (ready_resolve, ready_reject) = ready_callbacks.unwrap();
+
+        let backend = Rc::new(Backend {
+            worker: worker.clone(),
+            ready,
+            ready_reject,
+            pending: Rc::new(RefCell::new(HashMap::new())),
+            next_id: Cell::new(0),
+        });
+
+        let jobs = backend.pending.clone();
+        let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            if event.data().as_string().is_some_and(|x| x == READY) {
+                let _ = web_sys::Url::revoke_object_url(&url);
+                let _ = ready_resolve.call0(&JsValue::NULL);
+                return
+            }
+            let message = Uint8Array::new(&event.data()).to_vec();
+            let (id, payload) = message.split_at(8);
+            let id = u64::from_le_bytes(id.try_into().unwrap());
+            let reply = bincode::deserialize::<Reply>(payload).unwrap_or_else(|e| Reply::Malformed(e.to_string()));
+
+            let mut jobs = jobs.borrow_mut();
+            match reply {
+                Reply::Started => {
+                    if let Some(job) = jobs.get_mut(&id) {
+                        job.started = true;
+                    }
+                },
+                Reply::Progress(progress) => {
+                    if let Some(job) = jobs.get_mut(&id) {
+                        (job.on_progress.borrow_mut())(progress);
+                    }
+                },
+                Reply::Done(output) => {
+                    if let Some(job) = jobs.remove(&id) {
+                        let _ = job.sender.send(Ok(output));
+                    }
+                },
+                Reply::Malformed(message) => {
+                    if let Some(job) = jobs.remove(&id) {
+                        let _ = job.sender.send(Err(WorkerErr{ code: 2, message }));
+                    }
+                },
+            }
+        });
+        worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        // Failing to load the script or a crash while running a job ends
+        // up here; fail everything in flight and start over next time.
+        let crashed = backend.clone();
+        let onerror = Closure::<dyn FnMut(web_sys::Event)>::new(move |event: web_sys::Event| {
+            // Script loading failures are plain Events without a message
+            let reason = js_sys::Reflect::get(&event, &"message".into()).ok().and_then(|x| x.as_string());
+            crashed.stop(WorkerErr{ code: 1, message: "Worker error: ".to_string() + &reason.unwrap_or("worker stopped unexpectedly.".to_string()) });
+            STATE.with(|state| {
+                let is_current = matches!(&*state.borrow(), State::Running(current) if Rc::ptr_eq(current, &crashed));
+                if is_current {
+                    *state.borrow_mut() = State::Idle;
+                }
+            });
+        });
+        worker.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+        onerror.forget();
+
+        worker.post_message(&wasm_bindgen::module())?;
+
+        Ok(backend)
+    }
+
+    fn backend() -> Option<Rc<Backend>> {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            if let State::Idle = *state {
+                *state = match spawn() {
+                    Ok(backend) => State::Running(backend),
+                    Err(_) => State::Unavailable,
+                };
+            }
+            match &*state {
+                State::Running(backend) => Some(backend.clone()),
+                _ => None,
+            }
+        })
+    }
+
+    // Stops the worker, failing the jobs in flight with `error`. The next
+    // job starts a new worker.
+    fn stop(error: WorkerErr) {
+        let stopped = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            match *state {
+                State::Running(_) => std::mem::replace(&mut *state, State::Idle),
+                _ => State::Idle,
+            }
+        });
+        if let State::Running(backend) = stopped {
+            backend.stop(error);
+        }
+    }
+
+    /// Stops the worker and fails all jobs in flight with
+    /// [WorkerErr::cancelled]. The next job starts a new worker.
+    pub fn cancel() {
+        stop(WorkerErr::cancelled());
+    }
+
+    // Cancels the job `id` if the future waiting for it is dropped, for
+    // example when the resource that submitted it restarts.
+    struct JobGuard {
+        backend: Rc<Backend>,
+        id: u64,
+    }
+
+    impl Drop for JobGuard {
+        fn drop(&mut self) {
+            let job = self.backend.pending.borrow_mut().remove(&self.id);
+            match job {
+                Some(job) if job.started => {
+                    // A running job can only be stopped with the worker,
+                    // the other jobs are sent to a new one.
+                    let is_current = STATE.with(|state| matches!(&*state.borrow(), State::Running(current) if Rc::ptr_eq(current, &self.backend)));
+                    if is_current {
+                        stop(WorkerErr::restarted());
+                    }
+                },
+                Some(_) => {
+                    let _ = self.backend.worker.post_message(&Uint8Array::from(&self.id.to_le_bytes()[..]));
+                },
+                None => (),
+            }
+        }
+    }
+
+    /// Sends `job` to the execution backend, calling `on_progress` with
+    /// any progress the job reports. Dropping the returned future cancels
+    /// the job.
+    ///
+    /// The inputs are copied out of `job` before this returns, so the returned
+    /// future does not hold on to any borrowed data (or signal guards).
+    pub fn submit(
+        job: &Job,
+        on_progress: impl FnMut(Progress) + 'static,
+    ) -> impl Future<Output = Result<JobOutput, WorkerErr>> {
+        // Reserve room for the job id in front of the payload
+        let mut message: Vec<u8> = vec![0; 8];
+        let serialized = bincode::serialize_into(&mut message, job);
+        let on_progress: Rc<RefCell<dyn FnMut(Progress)>> = Rc::new(RefCell::new(on_progress));
+
+        async move {
+            serialized?;
+            loop {
+                let backend = match backend() {
+                    Some(backend) => backend,
+                    None => return run_inline(&message[8..], &mut |x| (on_progress.borrow_mut())(x)).await,
+                };
+
+                if let Err(e) = JsFuture::from(backend.ready.clone()).await {
+                    match e.as_string().as_deref() {
+                        Some(CANCELLED) => return Err(WorkerErr::cancelled()),
+                        Some(RESTARTED) => continue,
+                        _ => (),
+                    }
+                    STATE.with(|state| *state.borrow_mut() = State::Unavailable);
+                    return run_inline(&message[8..], &mut |x| (on_progress.borrow_mut())(x)).await
+                }
+
+                let id = backend.next_id.get();
+                backend.next_id.set(id + 1);
+                message[0..8].copy_from_slice(&id.to_le_bytes());
+
+                let (sender, receiver) = oneshot::channel();
+                backend.pending.borrow_mut().insert(id, PendingJob { sender, on_progress: on_progress.clone(), started: false });
+                let _guard = JobGuard { backend: backend.clone(), id };
+                let payload = Uint8Array::from(message.as_slice());
+                backend.worker.post_message_with_transfer(&payload, &Array::of1(&payload.buffer()))?;
+
+                match receiver.await {
+                    Ok(Err(e)) if e == WorkerErr::restarted() => continue,
+                    Ok(res) => return res,
+                    Err(_) => return Err(WorkerErr::cancelled()),
+                }
+            }
+        }
+    }
+
+    async fn run_inline(
+        payload: &[u8],
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<JobOutput, WorkerErr> {
+        let job = bincode::deserialize::<Job>(payload)?;
+        Ok(execute(&job, on_progress).await)
+    }
+}